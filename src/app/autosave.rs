@@ -0,0 +1,181 @@
+//! Crash-safe saving of app states.
+//!
+//! States are serialized on the main thread, then written by a background
+//! thread. Each file is written to a temporary file and renamed over the old
+//! one, so a crash while saving leaves the last complete save in place. The
+//! previous saves are kept as `<state>.1`, `<state>.2` and so on, newest first.
+
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// A serialized state and where it goes.
+pub type SavedState = (PathBuf, Vec<u8>);
+
+/// Path of the `n`th most recent backup of a state file.
+pub fn backup_path<P: AsRef<Path>>(path: P, n: usize) -> PathBuf {
+    let mut name = OsString::from(path.as_ref());
+    name.push(format!(".{}", n));
+    PathBuf::from(name)
+}
+
+/// Replace a file without ever leaving it partially written, keeping up to
+/// `backups` previous versions.
+pub fn write_atomic<P: AsRef<Path>>(path: P, data: &[u8], backups: usize) -> io::Result<()> {
+    let path = path.as_ref();
+    let mut temp = OsString::from(path);
+    temp.push(".tmp");
+    let temp = PathBuf::from(temp);
+    {
+        let mut file = File::create(&temp)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+    }
+
+    if backups > 0 && path.is_file() {
+        for n in (1..backups).rev() {
+            let from = backup_path(path, n);
+            if from.is_file() {
+                fs::rename(&from, backup_path(path, n + 1))?;
+            }
+        }
+        // Copied rather than moved, so that there is always a current save
+        fs::copy(path, backup_path(path, 1))?;
+    }
+
+    fs::rename(&temp, path)
+}
+
+/// Writes states on a background thread, and keeps track of when they are due.
+pub struct Autosaver {
+    pub interval: Duration,
+    last_save: Instant,
+    sender: Option<Sender<Vec<SavedState>>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Autosaver {
+    /// Start the writer thread. States are saved every `interval`, with up to
+    /// `backups` previous saves kept for each.
+    pub fn new(interval: Duration, backups: usize) -> Autosaver {
+        let (sender, receiver) = channel::<Vec<SavedState>>();
+        let thread = thread::Builder::new()
+            .name("autosave".to_owned())
+            .spawn(move || {
+                for states in receiver {
+                    for (path, data) in states {
+                        if let Err(e) = write_atomic(&path, &data, backups) {
+                            event!(Error, "state_write_failed", path = path, error = e.to_string());
+                        }
+                    }
+                }
+            })
+            .expect("Could not start autosave thread");
+        Autosaver {
+            interval,
+            last_save: Instant::now(),
+            sender: Some(sender),
+            thread: Some(thread),
+        }
+    }
+
+    /// If it has been `interval` since the last save.
+    pub fn due(&self) -> bool {
+        self.last_save.elapsed() >= self.interval
+    }
+
+    /// Queue states to be written.
+    pub fn save(&mut self, states: Vec<SavedState>) {
+        self.last_save = Instant::now();
+        if let Some(ref sender) = self.sender {
+            if sender.send(states).is_err() {
+                error!("Autosave thread stopped, states were not saved");
+            }
+        }
+    }
+
+    /// Wait for every queued state to be written.
+    pub fn finish(&mut self) {
+        self.sender.take();
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                error!("Autosave thread panicked");
+            }
+        }
+    }
+}
+
+impl Drop for Autosaver {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::io::Read;
+    use std::process;
+
+    /// An empty directory, removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> TempDir {
+            let dir = env::temp_dir().join(format!("vr-autosave-{}-{}", process::id(), name));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            TempDir(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn read(path: &Path) -> String {
+        let mut text = String::new();
+        File::open(path).unwrap().read_to_string(&mut text).unwrap();
+        text
+    }
+
+    #[test]
+    fn backups_are_rotated_newest_first() {
+        let dir = TempDir::new("rotate");
+        let path = dir.0.join("state.json");
+        for n in 1..5 {
+            write_atomic(&path, n.to_string().as_bytes(), 2).unwrap();
+        }
+        assert_eq!(read(&path), "4");
+        assert_eq!(read(&backup_path(&path, 1)), "3");
+        assert_eq!(read(&backup_path(&path, 2)), "2");
+        assert!(!backup_path(&path, 3).exists());
+        assert!(!dir.0.join("state.json.tmp").exists());
+    }
+
+    #[test]
+    fn no_backups_are_kept_when_disabled() {
+        let dir = TempDir::new("none");
+        let path = dir.0.join("state.json");
+        write_atomic(&path, b"1", 0).unwrap();
+        write_atomic(&path, b"2", 0).unwrap();
+        assert_eq!(read(&path), "2");
+        assert!(!backup_path(&path, 1).exists());
+    }
+
+    #[test]
+    fn the_first_save_has_no_backup() {
+        let dir = TempDir::new("first");
+        let path = dir.0.join("state.json");
+        write_atomic(&path, b"1", 3).unwrap();
+        assert_eq!(read(&path), "1");
+        assert!(!backup_path(&path, 1).exists());
+    }
+}
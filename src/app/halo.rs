@@ -1,51 +1,124 @@
 use std::collections::HashMap;
-use std::io::{Read, Write};
 use std::boxed::FnBox;
 
-use serde::{self, Serialize, Deserialize};
-use serde_json::{Deserializer, Serializer, Error as JsonError};
-use serde_json::de::IoRead as JsonRead;
+use serde_json::{Error as JsonError, Value};
 
-use nalgebra::{self as na, Vector3, Similarity3, Isometry3, Translation3, UnitQuaternion};
-use ncollide::shape::{Cuboid, Cylinder};
+use nalgebra::{self as na, Vector3, Similarity3, Isometry3, Translation3};
+use ncollide::shape::Cuboid;
 use nphysics3d::object::RigidBody;
 
 // Flight
-use flight::{UberMesh, Error};
+use flight::Error;
 
 // GFX
 use gfx;
-use app::App;
+use app::{App, AppInfo};
+use app::state::{self, VersionedState};
 
-use common::{open_object_directory, Common, CommonReply, Meta};
-use interact::ControllerIndex;
+use common::{Common, CommonReply, Meshes, Meta};
+use ui::{Toggle, Label};
 
-pub struct Halo<R: gfx::Resources> {
-    halo_mesh: UberMesh<R>,
+/// Turns a registered app on and off.
+struct AppToggle {
+    id: String,
+    name: String,
+    toggle: Toggle,
+}
+
+const HALO: &str = "assets/halo/";
+const HALO_SCALE: f32 = 0.5;
+
+pub struct Halo {
+    toggles: Vec<AppToggle>,
+    /// Loaded toggle positions, used when the toggles are created.
+    saved_positions: HashMap<String, Isometry3<f32>>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct HaloState {
-    pub active_apps: HashMap<String, bool>,
+    /// Only in version 1 states. Active apps are now settings.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub active_apps: Option<HashMap<String, bool>>,
+    #[serde(default)]
+    pub toggle_positions: HashMap<String, Isometry3<f32>>,
+}
+
+impl VersionedState for HaloState {
+    const VERSION: u32 = 2;
+
+    fn migrate(version: u32, state: Value) -> Result<Value, String> {
+        match version {
+            // The active apps are moved to the settings in `de_state`
+            1 => Ok(state),
+            _ => state::migrate_unversioned(version, state),
+        }
+    }
 }
 
-impl<R: gfx::Resources> Halo<R> {
-    pub fn new<F: gfx::Factory<R>>(factory: &mut F) -> Result<Self, Error> {
-        Ok(Halo { halo_mesh: open_object_directory(factory, "assets/halo/")? })
+impl Halo {
+    pub fn new<R, F>(factory: &mut F, meshes: &mut Meshes<R>) -> Result<Self, Error>
+        where R: gfx::Resources, F: gfx::Factory<R>
+    {
+        // The collider matches the halo mesh, which is drawn scaled down
+        meshes.load_scaled_object(factory, HALO, HALO_SCALE)?;
+        Ok(Halo {
+            toggles: Vec::new(),
+            saved_positions: HashMap::new(),
+        })
+    }
+
+    /// Create a toggle for every toggleable app that does not have one yet.
+    /// New toggles are spaced evenly in a circle around the halo.
+    fn sync_toggles(&mut self, apps: &[AppInfo]) {
+        let toggleable: Vec<_> = apps.iter().filter(|a| a.toggleable).collect();
+        let count = toggleable.len();
+        for (i, info) in toggleable.into_iter().enumerate() {
+            if self.toggles.iter().any(|t| t.id == info.id) {
+                continue;
+            }
+            let angle = i as f32 / count as f32 * 2. * ::std::f32::consts::PI;
+            let default = Isometry3::from_parts(
+                Translation3::new(2. * angle.cos(), 1., 2. * angle.sin()),
+                na::one(),
+            );
+            let pos = self.saved_positions.get(&info.id).cloned().unwrap_or(default);
+            self.toggles.push(AppToggle {
+                id: info.id.clone(),
+                name: info.name.clone(),
+                toggle: Toggle::new(pos, Vector3::new(0.25, 0.25, 0.25), true),
+            });
+        }
     }
 }
 
-impl<R: gfx::Resources + 'static, C: gfx::CommandBuffer<R> + 'static, W: Write, Re: Read> App<R, C, W, Re> for Halo<R> {
-    fn se_state(&self, serializer: &mut Serializer<W>, meta: &mut Meta) -> Result<<&mut Serializer<W> as serde::Serializer>::Ok, JsonError> {
+impl<R: gfx::Resources + 'static, C: gfx::CommandBuffer<R> + 'static> App<R, C> for Halo {
+    fn se_state(&self, _: &mut Meta) -> Result<Value, JsonError> {
         let state = HaloState {
-            active_apps: meta.active_apps.clone(),
+            active_apps: None,
+            toggle_positions: self.saved_positions.clone().into_iter()
+                .chain(self.toggles.iter().map(|t| (t.id.clone(), t.toggle.button.position)))
+                .collect(),
         };
-        state.serialize(serializer)
+        state::save(&state)
     }
 
-    fn de_state(&mut self, deserializer: &mut Deserializer<JsonRead<Re>>, meta: &mut Meta) -> Result<(), JsonError> {
-        let state = HaloState::deserialize(deserializer)?;
-        meta.active_apps = state.active_apps;
+    fn de_state(&mut self, saved: Value, meta: &mut Meta) -> Result<(), JsonError> {
+        let state: HaloState = state::load(saved)?;
+        if let Some(active_apps) = state.active_apps {
+            // Saves only list the apps registered when they were written, so
+            // only those settings are changed
+            for (id, active) in active_apps {
+                if meta.apps.iter().any(|a| a.id == id) {
+                    meta.set_active(&id, active);
+                }
+            }
+        }
+        for t in self.toggles.iter_mut() {
+            if let Some(pos) = state.toggle_positions.get(&t.id) {
+                t.toggle.button.position = *pos;
+            }
+        }
+        self.saved_positions = state.toggle_positions;
         Ok(())
     }
 
@@ -72,60 +145,63 @@ impl<R: gfx::Resources + 'static, C: gfx::CommandBuffer<R> + 'static, W: Write,
         common.painters.uber.draw(&mut common.draw_params, na::one(), &common.meshes.floor);
 
         // Draw torus
-        let torus = Cylinder::new(0.02, 0.5);
-        let torus = common.gurus
-            .interact
-            .primary
-            .pointing_laser(&Isometry3::from_parts(Translation3::new(0., 3., 0.), na::one()),
-                            &torus,
-                            true);
+        let halo_pos = Isometry3::from_parts(Translation3::new(0., 2.5, 0.), na::one());
+        let torus = match common.meshes.shape(HALO) {
+            Some(torus) => Some(common.gurus
+                .interact
+                .primary
+                .pointing_laser(&halo_pos, torus, true)),
+            None => None,
+        };
+
+        self.sync_toggles(&common.meta.apps);
+
+        // Toggle apps from the wrist menu
+        for t in &self.toggles {
+            if common.gurus.menu.entry(format!("halo/toggle/{}", t.id), format!("Toggle {}", t.name)) {
+                let active = common.meta.is_active(&t.id);
+                common.meta.set_active(&t.id, !active);
+                common.gurus.history.change(format!("toggle {}", t.name));
+            }
+        }
 
         // Setup toggle futures
-        let toggles = vec![
-            ("lets_get_physical", Translation3::new(2., 1., 0.)),
-            ("snowflakes", Translation3::new(0., 1., 2.)),
-        ];
-        let toggle_box_shape = Cuboid::new(Vector3::new(0.25, 0.25, 0.25));
-        let toggle_futures: Vec<_> = toggles.into_iter().flat_map(|(app, trans)| {
-            // Draw the toggles
-            common.painters.solid.draw(&mut common.draw_params,
-                                       na::convert(Similarity3::from_parts(trans, na::one(), 0.5)),
-                                       &common.meshes.wire_box);
-
-            // Return the future
-            (&[ControllerIndex::primary(), ControllerIndex::secondary()]).into_iter().map(|&idx| {
-                (app, idx,
-                 idx.guru(&mut common.gurus.interact).pointing_laser(&Isometry3::from_parts(trans, na::one()),
-                                                                     &toggle_box_shape, true))
-            }).collect::<Vec<_>>()
+        let toggle_futures: Vec<_> = self.toggles.iter_mut().map(|t| {
+            t.toggle.value = common.meta.is_active(&t.id);
+            let label_pos = t.toggle.button.position * Translation3::new(0., 0.35, 0.);
+            (&t.id, &t.name, label_pos, t.toggle.update(&mut common.gurus.interact))
         }).collect();
 
         Box::new(move |r: &mut CommonReply<_, _>| {
-            let _torus = torus(&r.reply.interact);
+            let _torus = torus.map(|t| t(&r.reply.interact));
 
             // Do the toggles
-            for (app, i, f) in toggle_futures.into_iter() {
-                let con = i.reply(&r.reply.interact);
-                if f(&r.reply.interact).is_some() && con.data.trigger > 0.5 &&
-                    con.data.trigger - con.data.trigger_delta < 0.5 {
-                    match r.meta.active_apps.get_mut(app) {
-                        Some(v) => {
-                            *v = !*v;
-                            *v
-                        },
-                        None => false,
-                    };
+            for (id, name, label_pos, f) in toggle_futures.into_iter() {
+                let active = f(r);
+                if r.meta.is_active(id) != active {
+                    r.meta.set_active(id, active);
+                    r.reply.history.change(format!("toggle {}", name));
+                }
+                match r.meshes.icon(id) {
+                    Some(icon) => r.painters.uber.draw(
+                        &mut r.draw_params,
+                        na::convert(Similarity3::from_parts(
+                            label_pos.translation,
+                            label_pos.rotation,
+                            0.3,
+                        )),
+                        icon,
+                    ),
+                    None => Label::new(name.as_str(), label_pos, 0.1).draw(r),
                 }
             }
 
             // Draw the halo
-            r.painters.uber.draw(&mut r.draw_params, na::convert(
-                Similarity3::from_parts(
-                    Translation3::new(0., 2.5, 0.),
-                    UnitQuaternion::from_axis_angle(&Vector3::y_axis(), 0.),
-                    0.5
-                )
-            ), &self.halo_mesh);
+            r.painters.uber.draw(
+                &mut r.draw_params,
+                na::convert(Similarity3::from_isometry(halo_pos, HALO_SCALE)),
+                r.meshes.object(HALO),
+            );
 
             for con in &[&r.reply.interact.primary, &r.reply.interact.secondary] {
                 r.painters.solid.draw(&mut r.draw_params, na::convert(
@@ -0,0 +1,242 @@
+//! Undo and redo, built on snapshots of every app's state.
+//!
+//! Each point in the history is a `Snapshot` taken right after a change was
+//! reported to the `HistoryGuru`. Undoing restores the snapshot before the
+//! current one.
+
+use std::collections::VecDeque;
+
+use flight::vr::MappedController;
+
+use common::gurus::interact::InteractGuru;
+use common::gurus::menu::MenuGuru;
+use super::snapshot::Snapshot;
+
+/// A point in the history.
+struct Entry {
+    /// What changed to reach this point.
+    label: String,
+    snapshot: Snapshot,
+}
+
+pub struct History {
+    /// The most points kept before the oldest are forgotten.
+    pub limit: usize,
+    past: VecDeque<Entry>,
+    current: Option<Entry>,
+    future: Vec<Entry>,
+}
+
+impl History {
+    pub fn new(limit: usize) -> History {
+        History {
+            limit,
+            past: VecDeque::new(),
+            current: None,
+            future: Vec::new(),
+        }
+    }
+
+    /// Forget everything, starting over from `snapshot`.
+    pub fn reset(&mut self, snapshot: Snapshot) {
+        self.past.clear();
+        self.future.clear();
+        self.current = Some(Entry { label: String::new(), snapshot });
+    }
+
+    /// Add a point after the current one. Anything that was undone can no
+    /// longer be redone.
+    pub fn record<L: Into<String>>(&mut self, label: L, snapshot: Snapshot) {
+        if let Some(current) = self.current.take() {
+            self.past.push_back(current);
+            while self.past.len() > self.limit {
+                self.past.pop_front();
+            }
+        }
+        self.future.clear();
+        self.current = Some(Entry { label: label.into(), snapshot });
+    }
+
+    /// What would be undone, if anything.
+    pub fn undo_label(&self) -> Option<&str> {
+        if self.past.is_empty() {
+            None
+        } else {
+            self.current.as_ref().map(|e| e.label.as_str())
+        }
+    }
+
+    /// What would be redone, if anything.
+    pub fn redo_label(&self) -> Option<&str> {
+        self.future.last().map(|e| e.label.as_str())
+    }
+
+    /// Step back, returning the snapshot to restore.
+    pub fn undo(&mut self) -> Option<&Snapshot> {
+        let previous = match self.past.pop_back() {
+            Some(e) => e,
+            None => return None,
+        };
+        if let Some(current) = self.current.take() {
+            self.future.push(current);
+        }
+        self.current = Some(previous);
+        self.current.as_ref().map(|e| &e.snapshot)
+    }
+
+    /// Step forward again, returning the snapshot to restore.
+    pub fn redo(&mut self) -> Option<&Snapshot> {
+        let next = match self.future.pop() {
+            Some(e) => e,
+            None => return None,
+        };
+        if let Some(current) = self.current.take() {
+            self.past.push_back(current);
+        }
+        self.current = Some(next);
+        self.current.as_ref().map(|e| &e.snapshot)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryAction {
+    Undo,
+    Redo,
+}
+
+/// Triggers undo and redo from the wrist menu, or from controller gestures:
+/// pressing both menu buttons at once undoes, and doing so while holding the
+/// secondary trigger redoes.
+///
+/// Apps use a single menu button to yank and spawn things, so apps see
+/// neither menu button from the moment both are pressed until both are
+/// released again.
+pub struct HistoryControls {
+    /// Both menu buttons were pressed and have not both been released.
+    chord: bool,
+}
+
+impl HistoryControls {
+    pub fn new() -> HistoryControls {
+        HistoryControls { chord: false }
+    }
+
+    /// Show the wrist menu entries and check for gestures.
+    pub fn update(
+        &mut self,
+        history: &History,
+        menu: &mut MenuGuru,
+        interact: &mut InteractGuru,
+        primary: &MappedController,
+        secondary: &MappedController,
+    )
+        -> Option<HistoryAction>
+    {
+        let mut action = None;
+        if let Some(label) = history.undo_label() {
+            if menu.entry("history/undo", format!("Undo {}", label)) {
+                action = Some(HistoryAction::Undo);
+            }
+        }
+        if let Some(label) = history.redo_label() {
+            if menu.entry("history/redo", format!("Redo {}", label)) {
+                action = Some(HistoryAction::Redo);
+            }
+        }
+
+        if primary.menu && secondary.menu && !self.chord {
+            self.chord = true;
+            action = Some(if secondary.trigger > 0.5 { HistoryAction::Redo } else { HistoryAction::Undo });
+        } else if !primary.menu && !secondary.menu {
+            self.chord = false;
+        }
+        if self.chord {
+            interact.primary.data.menu = false;
+            interact.secondary.data.menu = false;
+        }
+        action
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::Value;
+
+    use super::*;
+    use super::super::snapshot::MetaSnapshot;
+
+    fn snapshot(n: u64) -> Snapshot {
+        let mut apps = HashMap::new();
+        apps.insert("counter".to_owned(), Value::from(n));
+        Snapshot {
+            meta: MetaSnapshot { settings: Value::Null },
+            apps,
+        }
+    }
+
+    fn number(snapshot: Option<&Snapshot>) -> Option<u64> {
+        snapshot.and_then(|s| s.apps["counter"].as_u64())
+    }
+
+    #[test]
+    fn undo_and_redo() {
+        let mut history = History::new(10);
+        history.reset(snapshot(0));
+        history.record("one", snapshot(1));
+        history.record("two", snapshot(2));
+
+        assert_eq!(history.undo_label(), Some("two"));
+        assert_eq!(history.redo_label(), None);
+        assert_eq!(number(history.undo()), Some(1));
+        assert_eq!(number(history.undo()), Some(0));
+        assert_eq!(number(history.undo()), None);
+        assert_eq!(history.undo_label(), None);
+
+        assert_eq!(history.redo_label(), Some("one"));
+        assert_eq!(number(history.redo()), Some(1));
+        assert_eq!(number(history.redo()), Some(2));
+        assert_eq!(number(history.redo()), None);
+        assert_eq!(history.undo_label(), Some("two"));
+    }
+
+    #[test]
+    fn record_forgets_undone() {
+        let mut history = History::new(10);
+        history.reset(snapshot(0));
+        history.record("one", snapshot(1));
+        history.undo();
+        history.record("other", snapshot(3));
+
+        assert_eq!(history.redo_label(), None);
+        assert_eq!(number(history.redo()), None);
+        assert_eq!(number(history.undo()), Some(0));
+    }
+
+    #[test]
+    fn limit_forgets_oldest() {
+        let mut history = History::new(2);
+        history.reset(snapshot(0));
+        for n in 1..5 {
+            history.record(n.to_string(), snapshot(n));
+        }
+
+        assert_eq!(number(history.undo()), Some(3));
+        assert_eq!(number(history.undo()), Some(2));
+        assert_eq!(number(history.undo()), None);
+    }
+
+    #[test]
+    fn reset_forgets_everything() {
+        let mut history = History::new(10);
+        history.reset(snapshot(0));
+        history.record("one", snapshot(1));
+        history.record("two", snapshot(2));
+        history.undo();
+        history.reset(snapshot(5));
+
+        assert_eq!(history.undo_label(), None);
+        assert_eq!(history.redo_label(), None);
+    }
+}
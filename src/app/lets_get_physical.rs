@@ -1,81 +1,129 @@
-use std::io::{Read, Write};
 use std::boxed::FnBox;
 
-use serde::{self, Serialize, Deserialize};
-use serde_json::{Deserializer, Serializer, Error as JsonError};
-use serde_json::de::IoRead as JsonRead;
+use serde_json::{self, Error as JsonError, Value};
 
 use nalgebra::{self as na, Vector3, Isometry3, Translation3, UnitQuaternion};
-use ncollide::shape::{ShapeHandle, Compound, Cuboid, Cylinder};
 use nphysics3d::object::RigidBody;
 
 // Flight
-use flight::{UberMesh, Error};
+use flight::Error;
 
 // GFX
 use gfx;
 use app::App;
+use app::state::{self, VersionedState};
 
-use common::{open_object_directory, Common, CommonReply, Meta};
-use common::gurus::interact::GrabbablePhysicsState;
+use common::{Common, CommonReply, Meshes, Meta};
+use common::collision::CollisionShape;
+use common::gurus::interact::{GrabbablePhysicsState, GrabbableBodyState, Moveable};
+use common::gurus::physics::{BodyParams, BodyState};
 
-pub struct LetsGetPhysical<R: gfx::Resources> {
-    mjolnir: UberMesh<R>,
+const MJOLNIR: &str = "assets/hammer/";
+const MJOLNIR_PARAMS: BodyParams = BodyParams { density: 2330., restitution: 0.35, friction: 0.47 };
+
+pub struct LetsGetPhysical {
     grabbable_state: GrabbablePhysicsState,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct LetsGetPhysicalState {
+    hammer: GrabbableBodyState,
+}
+
+/// The layout before the hammer's motion was saved.
+#[derive(Deserialize)]
+struct LetsGetPhysicalStateV1 {
     location: Isometry3<f32>,
 }
 
-fn spawn_mjolnir() -> GrabbablePhysicsState {
-    let shapes = vec![(Isometry3::new(Vector3::new(0., -3., 0.) * 0.08, na::zero()),
-                       ShapeHandle::new(Cuboid::new(Vector3::new(2.0, 1.5, 1.5) * 0.08))),
-                      (Isometry3::new(Vector3::new(0., 1.25, 0.) * 0.08, na::zero()),
-                       ShapeHandle::new(Cylinder::new(3.25 * 0.08, 0.5 * 0.08)))];
-    let compound = Compound::new(shapes);
+impl VersionedState for LetsGetPhysicalState {
+    const VERSION: u32 = 2;
+
+    fn migrate(version: u32, state: Value) -> Result<Value, String> {
+        match version {
+            // The hammer starts at rest
+            1 => {
+                let old: LetsGetPhysicalStateV1 = serde_json::from_value(state).map_err(|e| e.to_string())?;
+                let hammer = GrabbableBodyState {
+                    params: MJOLNIR_PARAMS,
+                    body: BodyState::at(old.location),
+                    mov: Moveable::Free,
+                };
+                serde_json::to_value(LetsGetPhysicalState { hammer }).map_err(|e| e.to_string())
+            },
+            _ => state::migrate_unversioned(version, state),
+        }
+    }
+}
+
+/// Where the hammer starts, and is put back when reset.
+fn mjolnir_home() -> Isometry3<f32> {
+    Isometry3::new(Vector3::new(0., 2.5, 0.), na::zero())
+}
 
-    let mut mjolnir_body = RigidBody::new_dynamic(compound, 2330., 0.35, 0.47);
-    let location = Isometry3::new(Vector3::new(0., 2.5, 0.), na::zero());
-    mjolnir_body.set_transformation(location);
+fn spawn_mjolnir(shape: &CollisionShape) -> GrabbablePhysicsState {
+    let p = MJOLNIR_PARAMS;
+    let mut mjolnir_body = RigidBody::new_dynamic(shape.clone(), p.density, p.restitution, p.friction);
+    mjolnir_body.set_transformation(mjolnir_home());
 
     GrabbablePhysicsState::new_free(mjolnir_body)
 }
 
-impl<R: gfx::Resources> LetsGetPhysical<R> {
-    pub fn new<F: gfx::Factory<R>>(factory: &mut F) -> Result<Self, Error> {
+/// Put the hammer back where it started, keeping its current shape.
+fn put_back_mjolnir(state: &mut GrabbablePhysicsState) {
+    state.mov = Moveable::Free;
+    state.body.set_transformation(mjolnir_home());
+    state.body.set_lin_vel(na::zero());
+    state.body.set_ang_vel(na::zero());
+}
+
+impl LetsGetPhysical {
+    pub fn new<R, F>(factory: &mut F, meshes: &mut Meshes<R>) -> Result<Self, Error>
+        where R: gfx::Resources, F: gfx::Factory<R>
+    {
+        meshes.load_object(factory, MJOLNIR)?;
         Ok(LetsGetPhysical {
-            mjolnir: open_object_directory(factory, "assets/hammer/")?,
-            grabbable_state: spawn_mjolnir(),
+            grabbable_state: spawn_mjolnir(meshes.require_shape(MJOLNIR)?),
         })
     }
 }
 
-impl<R: gfx::Resources + 'static, C: gfx::CommandBuffer<R> + 'static, W: Write, Re: Read> App<R, C, W, Re>
-    for LetsGetPhysical<R> {
-    fn se_state(&self,
-                serializer: &mut Serializer<W>, _: &mut Meta)
-                -> Result<<&mut Serializer<W> as serde::Serializer>::Ok, JsonError> {
+impl<R: gfx::Resources + 'static, C: gfx::CommandBuffer<R> + 'static> App<R, C> for LetsGetPhysical {
+    fn se_state(&self, _: &mut Meta) -> Result<Value, JsonError> {
         let state = LetsGetPhysicalState {
-            location: *self.grabbable_state.body.position(),
+            hammer: self.grabbable_state.save(MJOLNIR_PARAMS),
         };
-        state.serialize(serializer)
+        state::save(&state)
     }
 
-    fn de_state(&mut self, deserializer: &mut Deserializer<JsonRead<Re>>, _: &mut Meta) -> Result<(), JsonError> {
-        let state = LetsGetPhysicalState::deserialize(deserializer)?;
-        self.grabbable_state.body.set_transformation(state.location);
+    fn de_state(&mut self, saved: Value, _: &mut Meta) -> Result<(), JsonError> {
+        let state: LetsGetPhysicalState = state::load(saved)?;
+        // The hammer is always made of `MJOLNIR_PARAMS`, so its body is reused
+        self.grabbable_state.restore(&state.hammer);
         Ok(())
     }
 
+    fn on_deactivate(&mut self, _: &mut Meta) {
+        put_back_mjolnir(&mut self.grabbable_state);
+    }
+
+    fn on_suspend(&mut self, _: &mut Meta) {
+        // Drop the hammer rather than leaving it stuck to a controller
+        self.grabbable_state.mov = Moveable::Free;
+    }
+
     fn update<'b>(&'b mut self,
                   common: &mut Common<R, C>)
                   -> Box<FnBox(&mut CommonReply<R, C>) + 'b> {
         // Reset if you throw it off the platform.
         // TODO: this doesn't work with alternate gravity
-        if self.grabbable_state.body.position().translation.vector.y < -10. {
-            self.grabbable_state = spawn_mjolnir();
+        let reset = common.gurus.menu.entry("lets_get_physical/reset", "Reset hammer");
+        if reset || self.grabbable_state.body.position().translation.vector.y < -10. {
+            // Respawn with the latest collision shape, if it still has one
+            match common.meshes.shape(MJOLNIR) {
+                Some(shape) => self.grabbable_state = spawn_mjolnir(shape),
+                None => put_back_mjolnir(&mut self.grabbable_state),
+            }
         }
 
         let gp = self.grabbable_state.update(&mut common.gurus.interact,
@@ -87,12 +135,11 @@ impl<R: gfx::Resources + 'static, C: gfx::CommandBuffer<R> + 'static, W: Write,
                                                      &Vector3::new(0., 0., -1.),
                                                  ).unwrap(),
                                              ),
-                                             0.2 / common.meta.physics_speed);
+                                             0.2 / common.meta.physics_speed());
 
-        let mjolnir = &self.mjolnir;
         Box::new(move |r: &mut CommonReply<R, C>| {
             let pos = gp(r);
-            r.painters.uber.draw(&mut r.draw_params, na::convert(pos), mjolnir);
+            r.painters.uber.draw(&mut r.draw_params, na::convert(pos), r.meshes.object(MJOLNIR));
         })
     }
 }
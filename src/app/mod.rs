@@ -1,9 +1,6 @@
-use std::io::{Read, Write};
 use std::boxed::FnBox;
 
-use serde;
-use serde_json::{Error, Serializer, Deserializer};
-use serde_json::de::IoRead as JsonRead;
+use serde_json::{Error, Value};
 
 use gfx;
 use common::{Common, CommonReply, Meta};
@@ -14,10 +11,37 @@ pub mod lets_get_physical;
 pub mod snowflakes;
 // pub mod workshop;
 pub mod settings;
+pub mod scene;
+pub mod registry;
+pub mod autosave;
+pub mod snapshot;
+pub mod history;
+pub mod state;
 
-pub trait App<R: gfx::Resources, C: gfx::CommandBuffer<R>, W: Write, Re: Read> {
+pub use self::registry::{AppRegistry, AppInfo};
+
+pub trait App<R: gfx::Resources, C: gfx::CommandBuffer<R>> {
     fn update<'a>(&'a mut self, common: &mut Common<R, C>) -> Box<FnBox(&mut CommonReply<R, C>) + 'a>;
 
-    fn se_state(&self, serializer: &mut Serializer<W>, meta: &mut Meta) -> Result<<&mut Serializer<W> as serde::Serializer>::Ok, Error>;
-    fn de_state(&mut self, deserializer: &mut Deserializer<JsonRead<Re>>, meta: &mut Meta) -> Result<(), Error>;
+    /// Save the app's state as a value. The shell chooses how it is written,
+    /// see `StateFormat`.
+    fn se_state(&self, meta: &mut Meta) -> Result<Value, Error>;
+    /// Restore a state saved by `se_state`.
+    fn de_state(&mut self, saved: Value, meta: &mut Meta) -> Result<(), Error>;
+
+    /// Called when the app starts receiving updates: at startup if it is
+    /// active, when the user turns it on, and when the session resumes.
+    fn on_activate(&mut self, _meta: &mut Meta) {}
+
+    /// Called when the user turns the app off. The app will not be updated
+    /// until it is activated again.
+    fn on_deactivate(&mut self, _meta: &mut Meta) {}
+
+    /// Called when updates pause for the whole session, e.g. when the headset
+    /// loses tracking. `on_activate` is called when they resume.
+    fn on_suspend(&mut self, _meta: &mut Meta) {}
+
+    /// Called on every app, active or not, before the shell saves states and
+    /// exits.
+    fn on_shutdown(&mut self, _meta: &mut Meta) {}
 }
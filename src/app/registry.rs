@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use gfx;
+use app::App;
+use app::autosave::SavedState;
+use app::snapshot::{Snapshot, MetaSnapshot};
+use app::state::{self, StateFormat};
+use common::Meta;
+use common::settings::{SettingsStore, Validation, app_active, app_environment};
+
+/// Describes an app known to the shell.
+#[derive(Debug, Clone)]
+pub struct AppInfo {
+    /// Unique identifier, also used in the key of `settings::app_active`.
+    pub id: String,
+    /// Name shown to the user.
+    pub name: String,
+    /// Object directory of the mesh representing the app, if any.
+    pub icon: Option<PathBuf>,
+    /// If the app is active when there is no saved state.
+    pub default_active: bool,
+    /// If the user can turn the app on and off.
+    pub toggleable: bool,
+    /// Where the app's state is saved, relative to the states directory and
+    /// without an extension. See `StateFormat::path`.
+    pub state_path: PathBuf,
+}
+
+impl AppInfo {
+    /// Describe a toggleable app that is active by default and saves its state
+    /// to `<id>` in the states directory.
+    pub fn new<I, N>(id: I, name: N) -> AppInfo
+        where I: Into<String>, N: Into<String>
+    {
+        let id = id.into();
+        AppInfo {
+            state_path: PathBuf::from(&id),
+            id: id,
+            name: name.into(),
+            icon: None,
+            default_active: true,
+            toggleable: true,
+        }
+    }
+}
+
+/// A registered app and its description.
+pub struct Registered<R, C>
+    where R: gfx::Resources, C: gfx::CommandBuffer<R>
+{
+    pub info: AppInfo,
+    pub app: Box<App<R, C>>,
+}
+
+/// All of the apps run by the shell, in update order.
+pub struct AppRegistry<R, C>
+    where R: gfx::Resources, C: gfx::CommandBuffer<R>
+{
+    apps: Vec<Registered<R, C>>,
+    /// Where app states are saved.
+    states_dir: PathBuf,
+}
+
+impl<R, C> AppRegistry<R, C>
+    where R: gfx::Resources, C: gfx::CommandBuffer<R>
+{
+    pub fn new<P: Into<PathBuf>>(states_dir: P) -> Self {
+        AppRegistry {
+            apps: Vec::new(),
+            states_dir: states_dir.into(),
+        }
+    }
+
+    /// Add an app to the end of the update order.
+    pub fn register<A>(&mut self, info: AppInfo, app: A) -> &mut Self
+        where A: App<R, C> + 'static
+    {
+        if self.get(&info.id).is_some() {
+            warn!("App \"{}\" registered more than once", info.id);
+        }
+        self.apps.push(Registered {
+            info,
+            app: Box::new(app),
+        });
+        self
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Registered<R, C>> {
+        self.apps.iter().find(|r| r.info.id == id)
+    }
+
+    /// Descriptions of every registered app.
+    pub fn infos(&self) -> Vec<AppInfo> {
+        self.apps.iter().map(|r| r.info.clone()).collect()
+    }
+
+    /// Define the settings that turn each app on and off and choose its
+    /// backdrop.
+    pub fn define_settings(&self, settings: &mut SettingsStore, environments: &[String]) {
+        let backdrops: Vec<String> = Some(String::new()).into_iter()
+            .chain(environments.iter().cloned())
+            .collect();
+        for r in &self.apps {
+            settings.define(&app_active(&r.info.id), r.info.default_active, Validation::Any);
+            settings.define(&app_environment(&r.info.id), String::new(), Validation::OneOf(backdrops.clone()));
+        }
+    }
+
+    pub fn iter(&self) -> ::std::slice::Iter<Registered<R, C>> {
+        self.apps.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> ::std::slice::IterMut<Registered<R, C>> {
+        self.apps.iter_mut()
+    }
+
+    /// Call `on_activate` or `on_deactivate` on every app whose active setting
+    /// differs from `previous`, then update `previous`.
+    pub fn sync_active(&mut self, meta: &mut Meta, previous: &mut HashMap<String, bool>) {
+        for r in self.apps.iter_mut() {
+            let active = meta.is_active(&r.info.id);
+            let was_active = previous.get(&r.info.id).cloned().unwrap_or(false);
+            match (was_active, active) {
+                (false, true) => {
+                    info!("Activating {}", r.info.id);
+                    r.app.on_activate(meta);
+                },
+                (true, false) => {
+                    info!("Deactivating {}", r.info.id);
+                    r.app.on_deactivate(meta);
+                },
+                _ => (),
+            }
+            previous.insert(r.info.id.clone(), active);
+        }
+    }
+
+    /// Call `on_suspend` on every active app.
+    pub fn suspend(&mut self, meta: &mut Meta) {
+        for r in self.apps.iter_mut() {
+            if meta.is_active(&r.info.id) {
+                r.app.on_suspend(meta);
+            }
+        }
+    }
+
+    /// Call `on_activate` on every active app after a suspension.
+    pub fn resume(&mut self, meta: &mut Meta) {
+        for r in self.apps.iter_mut() {
+            if meta.is_active(&r.info.id) {
+                r.app.on_activate(meta);
+            }
+        }
+    }
+
+    /// Call `on_shutdown` on every app.
+    pub fn shutdown(&mut self, meta: &mut Meta) {
+        for r in self.apps.iter_mut() {
+            r.app.on_shutdown(meta);
+        }
+    }
+
+    /// Load the state of every app that has one saved, see
+    /// `state::load_saved`. Apps without a readable state keep their defaults.
+    pub fn load_states(&mut self, meta: &mut Meta, format: StateFormat, backups: usize) {
+        for r in self.apps.iter_mut() {
+            let app = &mut r.app;
+            state::load_saved(
+                &format!("state for {}", r.info.id),
+                &self.states_dir.join(&r.info.state_path),
+                format,
+                backups,
+                |value| app.de_state(value, meta).map_err(|e| e.to_string()),
+            );
+        }
+    }
+
+    /// Serialize the state of every app. Apps that fail are skipped.
+    pub fn save_states(&self, meta: &mut Meta, format: StateFormat) -> Vec<SavedState> {
+        let mut states = Vec::with_capacity(self.apps.len());
+        for r in &self.apps {
+            let result = r.app.se_state(meta)
+                .map_err(|e| e.to_string())
+                .and_then(|value| format.encode(&value));
+            match result {
+                Ok(data) => states.push((format.path(self.states_dir.join(&r.info.state_path)), data)),
+                Err(e) => event!(Error, "state_save_failed", app = r.info.id, error = e.to_string()),
+            }
+        }
+        states
+    }
+
+    /// Capture the state of every app, and of `meta`.
+    pub fn snapshot(&self, meta: &mut Meta) -> Snapshot {
+        let mut apps = HashMap::new();
+        for r in &self.apps {
+            match r.app.se_state(meta) {
+                Ok(state) => { apps.insert(r.info.id.clone(), state); },
+                Err(e) => event!(Error, "state_save_failed", app = r.info.id, error = e.to_string()),
+            }
+        }
+        Snapshot {
+            meta: MetaSnapshot::new(meta),
+            apps,
+        }
+    }
+
+    /// Restore a snapshot. Apps missing from it keep their current state.
+    pub fn restore(&mut self, snapshot: &Snapshot, meta: &mut Meta) {
+        for r in self.apps.iter_mut() {
+            if let Some(state) = snapshot.apps.get(&r.info.id) {
+                if let Err(e) = r.app.de_state(state.clone(), meta) {
+                    event!(Warn, "state_restore_failed", app = r.info.id, error = e.to_string());
+                }
+            }
+        }
+        snapshot.meta.apply(meta);
+    }
+}
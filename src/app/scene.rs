@@ -0,0 +1,229 @@
+use std::boxed::FnBox;
+
+use serde_json::{self, Error as JsonError, Value};
+
+use nalgebra::{self as na, Isometry3, Similarity3};
+use nphysics3d::object::RigidBody;
+
+// Flight
+use flight::Error;
+
+// GFX
+use gfx;
+use app::App;
+use app::state::{self, VersionedState};
+
+use common::{Common, CommonReply, Meshes, Meta};
+use common::collision::{CollisionShape, open_collision};
+use common::gurus::interact::{GrabbablePhysicsState, Moveable};
+use common::gurus::physics::BodyState;
+use scene::{SceneDesc, ObjectDesc, Behavior};
+
+enum ObjectState {
+    Static,
+    Dynamic(RigidBody<f32>),
+    Grabbable(GrabbablePhysicsState),
+}
+
+struct SceneObject {
+    desc: ObjectDesc,
+    shape: Option<CollisionShape>,
+    state: ObjectState,
+}
+
+impl SceneObject {
+    fn spawn(mut desc: ObjectDesc) -> Result<SceneObject, Error> {
+        let shape_desc = desc.shape.clone().or_else(|| open_collision(&desc.asset));
+        if desc.behavior != Behavior::Static && shape_desc.as_ref().map_or(false, |s| s.is_static_only()) {
+            warn!("{} has a triangle mesh collider, so it can not move", desc.asset.display());
+            desc.behavior = Behavior::Static;
+        }
+        let shape = match shape_desc {
+            Some(s) => Some(s.compound(&desc.asset, desc.transform.scale)?),
+            None => None,
+        };
+        let state = match (desc.behavior, shape.as_ref()) {
+            (Behavior::Static, _) | (_, None) => ObjectState::Static,
+            (behavior, Some(shape)) => {
+                let m = desc.material;
+                let mut body = RigidBody::new_dynamic(shape.clone(), m.density, m.restitution, m.friction);
+                body.set_margin(0.00001);
+                body.set_transformation(desc.transform.isometry());
+                if behavior == Behavior::Grabbable {
+                    ObjectState::Grabbable(GrabbablePhysicsState::new_free(body))
+                } else {
+                    ObjectState::Dynamic(body)
+                }
+            },
+        };
+        Ok(SceneObject { desc, shape, state })
+    }
+
+    fn save(&self) -> SavedObject {
+        match self.state {
+            ObjectState::Static => SavedObject {
+                body: BodyState::at(self.desc.transform.isometry()),
+                mov: Moveable::Free,
+            },
+            ObjectState::Dynamic(ref body) => SavedObject {
+                body: BodyState::new(body),
+                mov: Moveable::Free,
+            },
+            ObjectState::Grabbable(ref g) => SavedObject {
+                body: BodyState::new(&g.body),
+                mov: g.mov.saved(),
+            },
+        }
+    }
+
+    fn restore(&mut self, saved: &SavedObject) {
+        match self.state {
+            ObjectState::Static => (),
+            ObjectState::Dynamic(ref mut body) => saved.body.apply(body),
+            ObjectState::Grabbable(ref mut g) => {
+                saved.body.apply(&mut g.body);
+                g.mov = saved.mov.clone();
+            },
+        }
+    }
+}
+
+/// Spawns the objects described by a scene file.
+pub struct Scene {
+    objects: Vec<SceneObject>,
+}
+
+/// The motion of an object. Materials always come from the scene file.
+#[derive(Serialize, Deserialize)]
+struct SavedObject {
+    body: BodyState,
+    #[serde(default)]
+    mov: Moveable,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SceneState {
+    /// Every object, in the order they appear in the scene file.
+    objects: Vec<SavedObject>,
+}
+
+/// The layout before object motion was saved.
+#[derive(Deserialize)]
+struct SceneStateV1 {
+    positions: Vec<Isometry3<f32>>,
+}
+
+impl VersionedState for SceneState {
+    const VERSION: u32 = 2;
+
+    fn migrate(version: u32, state: Value) -> Result<Value, String> {
+        match version {
+            // Objects start at rest
+            1 => {
+                let old: SceneStateV1 = serde_json::from_value(state).map_err(|e| e.to_string())?;
+                let objects = old.positions.into_iter()
+                    .map(|p| SavedObject { body: BodyState::at(p), mov: Moveable::Free })
+                    .collect();
+                serde_json::to_value(SceneState { objects }).map_err(|e| e.to_string())
+            },
+            _ => state::migrate_unversioned(version, state),
+        }
+    }
+}
+
+impl Scene {
+    pub fn new<R, F>(factory: &mut F, meshes: &mut Meshes<R>, desc: SceneDesc) -> Result<Self, Error>
+        where R: gfx::Resources, F: gfx::Factory<R>
+    {
+        let mut objects = Vec::with_capacity(desc.objects.len());
+        for o in desc.objects {
+            meshes.load_object(factory, &o.asset)?;
+            objects.push(SceneObject::spawn(o)?);
+        }
+        Ok(Scene { objects })
+    }
+}
+
+impl<R: gfx::Resources + 'static, C: gfx::CommandBuffer<R> + 'static> App<R, C> for Scene {
+    fn se_state(&self, _: &mut Meta) -> Result<Value, JsonError> {
+        let state = SceneState {
+            objects: self.objects.iter().map(|o| o.save()).collect(),
+        };
+        state::save(&state)
+    }
+
+    fn de_state(&mut self, saved: Value, _: &mut Meta) -> Result<(), JsonError> {
+        let state: SceneState = state::load(saved)?;
+        // Objects added to the scene file since the state was saved keep
+        // their authored positions
+        for (o, saved) in self.objects.iter_mut().zip(state.objects.iter()) {
+            o.restore(saved);
+        }
+        Ok(())
+    }
+
+    fn update<'b>(&'b mut self,
+                  common: &mut Common<R, C>)
+                  -> Box<FnBox(&mut CommonReply<R, C>) + 'b> {
+        let yank_speed = 0.2 / common.meta.physics_speed();
+        let mut futures: Vec<Box<FnBox(&mut CommonReply<R, C>) + 'b>> = Vec::new();
+
+        for o in self.objects.iter_mut() {
+            let asset = &o.desc.asset;
+            let scale = o.desc.transform.scale;
+            match o.state {
+                ObjectState::Static => {
+                    let pos = o.desc.transform.isometry();
+                    if let Some(ref shape) = o.shape {
+                        common.gurus.interact.primary.laser(&pos, shape);
+                        common.gurus.interact.secondary.laser(&pos, shape);
+
+                        let m = o.desc.material;
+                        let mut body = RigidBody::new_static(shape.clone(), m.restitution, m.friction);
+                        body.set_transformation(pos);
+                        body.set_margin(0.00001);
+                        common.gurus.physics.body(body);
+                    }
+                    common.painters.uber.draw(
+                        &mut common.draw_params,
+                        na::convert(Similarity3::from_isometry(pos, scale)),
+                        common.meshes.object(asset),
+                    );
+                },
+                ObjectState::Dynamic(ref mut body) => {
+                    let phys = common.gurus.physics.body(body.clone());
+                    futures.push(Box::new(move |r: &mut CommonReply<R, C>| {
+                        *body = phys(&r.reply.physics);
+                        r.painters.uber.draw(
+                            &mut r.draw_params,
+                            na::convert(Similarity3::from_isometry(*body.position(), scale)),
+                            r.meshes.object(asset),
+                        );
+                    }));
+                },
+                ObjectState::Grabbable(ref mut g) => {
+                    let gp = g.update(
+                        &mut common.gurus.interact,
+                        &mut common.gurus.physics,
+                        Isometry3::identity(),
+                        yank_speed,
+                    );
+                    futures.push(Box::new(move |r: &mut CommonReply<R, C>| {
+                        let pos = gp(r);
+                        r.painters.uber.draw(
+                            &mut r.draw_params,
+                            na::convert(Similarity3::from_isometry(pos, scale)),
+                            r.meshes.object(asset),
+                        );
+                    }));
+                },
+            }
+        }
+
+        Box::new(move |r: &mut CommonReply<R, C>| {
+            for f in futures {
+                FnBox::call_box(f, (&mut *r, ));
+            }
+        })
+    }
+}
@@ -1,96 +1,290 @@
-use std::io::{Read, Write};
+use std::collections::HashMap;
 use std::boxed::FnBox;
 
-use serde::{self, Serialize, Deserialize};
-use serde_json::{Deserializer, Serializer, Error as JsonError};
-use serde_json::de::IoRead as JsonRead;
+use serde_json::{Error as JsonError, Value};
 
 use nalgebra::{Vector3, Isometry3, Translation3, UnitQuaternion};
 
 // GFX
 use gfx;
 use app::App;
-use ui::Slider;
+use app::state::{self, VersionedState};
+use ui::{Slider, SliderRange, SliderEvent, Button, Checkbox, Dropdown, NumericStepper, Label, Panel, Stack};
 
 use common::{Common, CommonReply, Meta};
+use common::settings::{self, MIN_PHYSICS_SPEED, MAX_PHYSICS_SPEED};
 
 pub struct Settings {
+    pub panel: Panel<Stack>,
     pub speed: Slider,
     pub length: Slider,
+    pub paused: Checkbox,
+    pub reset: Button,
+    /// Whose environment `environment` chooses: zero for the shell, otherwise
+    /// the backdrop of a toggleable app.
+    pub backdrop_for: NumericStepper,
+    pub environment: Dropdown,
+    reset_pending: bool,
+    /// If the widgets have shown the shared settings yet.
+    synced: bool,
+    /// The app whose backdrop `environment` showed last frame.
+    shown_target: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct SettingsState {
-    pub speed: f32,
     pub length: f32,
-    pub speed_slider_pos: Isometry3<f32>,
-    pub length_slider_pos: Isometry3<f32>,
+    #[serde(default)]
+    pub panel_pos: Option<Isometry3<f32>>,
+    /// Only in version 1 states. The physics speed, pause, environment and
+    /// backdrops are now shared settings.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub speed: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub paused: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub environment: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backdrops: Option<HashMap<String, String>>,
 }
 
+impl VersionedState for SettingsState {
+    const VERSION: u32 = 2;
+
+    fn migrate(version: u32, state: Value) -> Result<Value, String> {
+        match version {
+            // The shared settings are moved to `Meta::settings` in `de_state`
+            1 => Ok(state),
+            _ => state::migrate_unversioned(version, state),
+        }
+    }
+}
+
+const DEFAULT_SPEED: f32 = 1.;
+const DEFAULT_LENGTH: f32 = 0.5;
+
 impl Settings {
     pub fn new() -> Self {
-        Settings {
-            speed: Slider::new(Isometry3::from_parts(
-                    Translation3::new(0., 1.5, 0.),
-                    UnitQuaternion::rotation_between(
-                        &Vector3::new(0., 0., 1.),
-                        &Vector3::new(1., 1., 1.),
-                    ).unwrap(),
+        // Widgets are positioned by the panel
+        let mut settings = Settings {
+            panel: Panel::new(
+                Isometry3::from_parts(
+                    Translation3::new(0., 1.4, -1.),
+                    UnitQuaternion::from_axis_angle(&Vector3::x_axis(), -0.3),
                 ),
-                0.15,
-                0.50,
-                0.20,
+                Stack::vertical(0.05, 0.08),
+            ),
+            speed: Slider::new(Isometry3::identity(), 0.15, 0.50, 0.20, DEFAULT_SPEED),
+            length: Slider::new(Isometry3::identity(), 0.15, 0.50, 0.20, DEFAULT_LENGTH),
+            paused: Checkbox::new(Isometry3::identity(), 0.08, false),
+            reset: Button::new(Isometry3::identity(), Vector3::new(0.1, 0.05, 0.05)),
+            // The maximum is the number of toggleable apps
+            backdrop_for: NumericStepper::new(
+                Button::new(Isometry3::identity(), Vector3::new(0.1, 0.03, 0.03)),
+                0.,
+                0.,
                 1.,
+                0.,
             ),
-            length: Slider::new(Isometry3::from_parts(
-                    Translation3::new(0.5, 1.5, -0.5),
-                    UnitQuaternion::rotation_between(
-                        &Vector3::new(0., 0., 1.),
-                        &Vector3::new(1., 1., 1.),
-                    ).unwrap(),
-                ),
-                0.15,
-                0.50,
-                0.20,
-                0.5,
+            // Options are filled in from `Meta::environments`
+            environment: Dropdown::new(
+                Button::new(Isometry3::identity(), Vector3::new(0.15, 0.04, 0.03)),
+                Vec::new(),
+                0,
             ),
-        }
+            reset_pending: false,
+            synced: false,
+            shown_target: None,
+        };
+        settings.speed.label = "Speed".to_owned();
+        settings.speed.unit = "x".to_owned();
+        settings.speed.range = SliderRange::logarithmic(MIN_PHYSICS_SPEED, MAX_PHYSICS_SPEED);
+        settings.length.label = "Length".to_owned();
+        settings.length.unit = "m".to_owned();
+        settings.length.precision = 1;
+        settings.length.range = SliderRange {
+            step: Some(0.1),
+            ..SliderRange::linear(0.2, 0.8)
+        };
+        settings
     }
 }
 
-impl<R: gfx::Resources + 'static, C: gfx::CommandBuffer<R> + 'static, W: Write, Re: Read> App<R, C, W, Re>
-    for Settings {
-    fn se_state(&self,
-                serializer: &mut Serializer<W>, _: &mut Meta)
-                -> Result<<&mut Serializer<W> as serde::Serializer>::Ok, JsonError> {
+impl<R: gfx::Resources + 'static, C: gfx::CommandBuffer<R> + 'static> App<R, C> for Settings {
+    fn se_state(&self, _: &mut Meta) -> Result<Value, JsonError> {
         let state = SettingsState {
-            speed: self.speed.value,
             length: self.length.value,
-            speed_slider_pos: self.speed.position,
-            length_slider_pos: self.length.position,
+            panel_pos: Some(self.panel.position),
+            speed: None,
+            paused: None,
+            environment: None,
+            backdrops: None,
         };
-        state.serialize(serializer)
+        state::save(&state)
     }
 
-    fn de_state(&mut self, deserializer: &mut Deserializer<JsonRead<Re>>, _: &mut Meta) -> Result<(), JsonError> {
-        let state = SettingsState::deserialize(deserializer)?;
-        self.speed.value = state.speed;
-        self.length.value = state.length;
-        self.speed.position = state.speed_slider_pos;
-        self.length.position = state.length_slider_pos;
+    fn de_state(&mut self, saved: Value, meta: &mut Meta) -> Result<(), JsonError> {
+        let state: SettingsState = state::load(saved)?;
+        self.length.value = self.length.range.snap(state.length);
+        if let Some(pos) = state.panel_pos {
+            self.panel.position = pos;
+        }
+        if let Some(speed) = state.speed {
+            meta.settings.set(&settings::PHYSICS_SPEED, self.speed.range.snap(speed));
+        }
+        if let Some(paused) = state.paused {
+            meta.settings.set(&settings::PHYSICS_PAUSED, paused);
+        }
+        if let Some(env) = state.environment {
+            meta.settings.set(&settings::ENVIRONMENT, env);
+        }
+        for (id, env) in state.backdrops.unwrap_or_default() {
+            if meta.apps.iter().any(|a| a.id == id) {
+                meta.settings.set(&settings::app_environment(&id), env);
+            }
+        }
         Ok(())
     }
 
     fn update<'b>(&'b mut self,
                   common: &mut Common<R, C>)
                   -> Box<FnBox(&mut CommonReply<R, C>) + 'b> {
-        self.speed.length = 0.2 + 0.6 * self.length.value;
+        let was_reset = self.reset_pending;
+        if was_reset {
+            common.meta.settings.reset(&settings::PHYSICS_SPEED);
+            common.meta.settings.reset(&settings::PHYSICS_PAUSED);
+            self.length.reset();
+            self.reset_pending = false;
+        }
+
+        // Show the shared settings when anything else changes them
+        let refresh = !self.synced || was_reset;
+        if refresh || common.meta.settings.changed(&settings::PHYSICS_SPEED) {
+            self.speed.value = common.meta.settings.get(&settings::PHYSICS_SPEED);
+        }
+        if refresh || common.meta.settings.changed(&settings::PHYSICS_PAUSED) {
+            self.paused.toggle.value = common.meta.settings.get(&settings::PHYSICS_PAUSED);
+        }
+        self.synced = true;
+
+        self.speed.length = self.length.value;
+
+        // Apps have a first option to use the shell's environment
+        let targets: Vec<(String, String)> = common.meta.apps.iter()
+            .filter(|a| a.toggleable)
+            .map(|a| (a.id.clone(), a.name.clone()))
+            .collect();
+        self.backdrop_for.max = targets.len() as f32;
+        let target = match self.backdrop_for.value.round() as usize {
+            0 => None,
+            i => targets.get(i - 1).cloned(),
+        };
+        let offset = if target.is_some() { 1 } else { 0 };
+        let mut options: Vec<String> = Vec::new();
+        if target.is_some() {
+            options.push("Default".to_owned());
+        }
+        options.extend(common.meta.environments.iter().cloned());
+        if self.environment.options != options {
+            self.environment.options = options;
+        }
+        let env_key = match target {
+            Some((ref id, _)) => settings::app_environment(id),
+            None => settings::ENVIRONMENT,
+        };
+        let target_id = target.as_ref().map(|&(ref id, _)| id.clone());
+        if refresh || target_id != self.shown_target || common.meta.settings.changed(&env_key) {
+            let current_env = common.meta.settings.get(&env_key);
+            if current_env.is_empty() {
+                self.environment.selected = 0;
+            } else if let Some(i) = common.meta.environments.iter().position(|e| *e == current_env) {
+                self.environment.selected = i + offset;
+            }
+        }
+        self.shown_target = target_id;
+
+        // The environment list hangs below its header, so it goes last
+        let panel = self.panel.update(
+            &mut common.gurus.interact,
+            &mut [
+                &mut self.speed,
+                &mut self.length,
+                &mut self.paused,
+                &mut self.reset,
+                &mut self.backdrop_for,
+                &mut self.environment,
+            ],
+        );
+
+        let reset_label = Label::new("Reset", self.reset.position * Translation3::new(0., 0.08, 0.), 0.05);
+        let paused_label = Label::new(
+            "Pause",
+            self.paused.toggle.button.position * Translation3::new(0., 0.12, 0.),
+            0.05,
+        );
+        let backdrop_label = Label::new(
+            match target {
+                Some((_, ref name)) => format!("Backdrop for {}", name),
+                None => "Backdrop".to_owned(),
+            },
+            self.backdrop_for.body.position * Translation3::new(0., 0.06, 0.),
+            0.04,
+        );
         let speed = self.speed.update(&mut common.gurus.interact);
         let length = self.length.update(&mut common.gurus.interact);
+        let was_paused = self.paused.toggle.value;
+        let paused = self.paused.update(&mut common.gurus.interact);
+        let reset = self.reset.update(&mut common.gurus.interact, false);
+        let backdrop_for = self.backdrop_for.update(&mut common.gurus.interact);
+        let environments = common.meta.environments.clone();
+        let environment = self.environment.update(&mut common.gurus.interact);
 
+        let reset_pending = &mut self.reset_pending;
         Box::new(move |r: &mut CommonReply<_, _>| {
-            r.meta.physics_speed = speed(r);
-            length(r);
+            panel(r);
+            let speed = speed(r);
+            for event in &speed.events {
+                if let &SliderEvent::Released(v) = event {
+                    info!("Physics speed set to {}", v);
+                    r.reply.history.change("physics speed");
+                }
+            }
+            let speed = speed.value;
+            let length = length(r);
+            for event in &length.events {
+                if let &SliderEvent::Released(_) = event {
+                    r.reply.history.change("speed slider length");
+                }
+            }
+            let paused = paused(r);
+            if paused != was_paused {
+                r.reply.history.change(if paused { "pause" } else { "unpause" });
+            }
+            r.meta.settings.set(&settings::PHYSICS_SPEED, speed);
+            r.meta.settings.set(&settings::PHYSICS_PAUSED, paused);
+            // The sliders are borrowed until the next frame
+            *reset_pending = reset(r);
+            if *reset_pending {
+                r.reply.history.change("reset settings");
+            }
+            reset_label.draw(r);
+            paused_label.draw(r);
+            backdrop_label.draw(r);
+            backdrop_for(r);
+
+            // An empty backdrop shows the shell's environment
+            let choice = environment(r);
+            let env = if choice < offset {
+                Some(String::new())
+            } else {
+                environments.get(choice - offset).cloned()
+            };
+            if let Some(env) = env {
+                if r.meta.settings.set(&env_key, env.clone()) {
+                    info!("Environment of {} set to {:?}", env_key.name, env);
+                    r.reply.history.change("environment");
+                }
+            }
         })
     }
 }
@@ -0,0 +1,194 @@
+//! Named snapshots of the whole world.
+//!
+//! A snapshot holds the state of every app and the parts of `Meta` that are
+//! not derived from the registry. Each one is a single file in `SNAPSHOT_DIR`
+//! inside the states directory, written in any `StateFormat`.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use serde_json::{self, Value};
+
+use common::Meta;
+use common::gurus::menu::MenuGuru;
+use super::autosave::write_atomic;
+use super::state::StateFormat;
+
+/// Where snapshots are saved, inside the states directory.
+pub const SNAPSHOT_DIR: &str = "snapshots";
+
+/// The directory snapshots are saved in.
+pub fn snapshot_dir(states_dir: &Path) -> PathBuf {
+    states_dir.join(SNAPSHOT_DIR)
+}
+
+/// The parts of `Meta` saved in a snapshot.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetaSnapshot {
+    /// Saved by `SettingsStore::save`. Snapshots from before settings were
+    /// shared don't have one, and keep the current settings.
+    #[serde(default)]
+    pub settings: Value,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub meta: MetaSnapshot,
+    /// Saved app states, by app id.
+    pub apps: HashMap<String, Value>,
+}
+
+impl MetaSnapshot {
+    pub fn new(meta: &Meta) -> MetaSnapshot {
+        MetaSnapshot {
+            settings: match meta.settings.save() {
+                Ok(v) => v,
+                Err(e) => {
+                    event!(Error, "settings_save_failed", error = e.to_string());
+                    Value::Null
+                },
+            },
+        }
+    }
+
+    /// Apply to `meta`.
+    pub fn apply(&self, meta: &mut Meta) {
+        if self.settings.is_null() {
+            return;
+        }
+        if let Err(e) = meta.settings.load(self.settings.clone()) {
+            event!(Warn, "settings_restore_failed", error = e.to_string());
+        }
+    }
+}
+
+/// If a name can be used for a snapshot. Names become file names, so they
+/// are limited to letters, numbers, `-` and `_`.
+pub fn valid_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Find the file a snapshot is saved in, and its format.
+fn find(dir: &Path, name: &str) -> Option<(StateFormat, PathBuf)> {
+    let base = dir.join(name);
+    StateFormat::ALL.iter()
+        .map(|f| (*f, f.path(&base)))
+        .find(|&(_, ref p)| p.is_file())
+}
+
+/// Names of every saved snapshot, sorted.
+pub fn list(dir: &Path) -> Vec<String> {
+    let entries = match fs::read_dir(dir) {
+        Ok(e) => e,
+        Err(_) => return Vec::new(),
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| {
+            let ext = p.extension().and_then(|e| e.to_str());
+            StateFormat::ALL.iter().any(|f| Some(f.extension()) == ext)
+        })
+        .filter_map(|p| p.file_stem().and_then(|s| s.to_str()).map(|s| s.to_owned()))
+        .filter(|n| valid_name(n))
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+pub fn save(dir: &Path, name: &str, snapshot: &Snapshot, format: StateFormat) -> Result<(), String> {
+    if !valid_name(name) {
+        return Err(format!("invalid snapshot name \"{}\"", name));
+    }
+    let value = serde_json::to_value(snapshot).map_err(|e| e.to_string())?;
+    let data = format.encode(&value)?;
+    fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    let base = dir.join(name);
+    let path = format.path(&base);
+    write_atomic(&path, &data, 0).map_err(|e| e.to_string())?;
+    // Remove copies in other formats, which would be found instead
+    for f in &StateFormat::ALL {
+        let other = f.path(&base);
+        if other != path && other.is_file() {
+            fs::remove_file(other).map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+pub fn open(dir: &Path, name: &str) -> Result<Snapshot, String> {
+    let (format, path) = match find(dir, name) {
+        Some(f) => f,
+        None => return Err(format!("no snapshot named \"{}\"", name)),
+    };
+    let mut data = Vec::new();
+    File::open(&path)
+        .and_then(|mut f| f.read_to_end(&mut data))
+        .map_err(|e| e.to_string())?;
+    let value = format.decode(&data)?;
+    serde_json::from_value(value).map_err(|e| e.to_string())
+}
+
+pub fn delete(dir: &Path, name: &str) -> io::Result<()> {
+    match find(dir, name) {
+        Some((_, path)) => fs::remove_file(path),
+        None => Err(io::Error::new(io::ErrorKind::NotFound, format!("no snapshot named \"{}\"", name))),
+    }
+}
+
+/// Something the user asked to do with snapshots.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SnapshotAction {
+    Save(String),
+    Load(String),
+    Delete(String),
+}
+
+/// Wrist menu entries for saving, loading and deleting snapshots.
+pub struct SnapshotMenu {
+    /// Where the snapshots are saved, see `snapshot_dir`.
+    dir: PathBuf,
+    names: Vec<String>,
+}
+
+impl SnapshotMenu {
+    pub fn new(dir: PathBuf) -> SnapshotMenu {
+        let names = list(&dir);
+        SnapshotMenu { dir, names }
+    }
+
+    /// Read the list of snapshots again, after one is saved or deleted.
+    pub fn refresh(&mut self) {
+        self.names = list(&self.dir);
+    }
+
+    /// A name that isn't taken, for snapshots saved from the menu.
+    fn next_name(&self) -> String {
+        (1..)
+            .map(|i| format!("snapshot-{}", i))
+            .find(|n| !self.names.contains(n))
+            .unwrap()
+    }
+
+    /// Show the entries, and return what was chosen on the last frame.
+    pub fn update(&mut self, menu: &mut MenuGuru) -> Option<SnapshotAction> {
+        let mut action = None;
+        if menu.entry("snapshots/save", "Save snapshot") {
+            action = Some(SnapshotAction::Save(self.next_name()));
+        }
+        for name in &self.names {
+            if menu.entry(format!("snapshots/load/{}", name), format!("Load {}", name)) {
+                action = Some(SnapshotAction::Load(name.clone()));
+            }
+        }
+        for name in &self.names {
+            if menu.entry(format!("snapshots/delete/{}", name), format!("Delete {}", name)) {
+                action = Some(SnapshotAction::Delete(name.clone()));
+            }
+        }
+        action
+    }
+}
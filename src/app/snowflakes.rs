@@ -1,24 +1,34 @@
-use std::io::{Read, Write};
 use std::boxed::FnBox;
 
-use serde::{self, Serialize, Deserialize};
-use serde_json::{Deserializer, Serializer, Error as JsonError};
-use serde_json::de::IoRead as JsonRead;
+use serde_json::{self, Error as JsonError, Value};
 
 use nalgebra::{self as na, Vector3, Isometry3, Translation3};
-use ncollide::shape::{ShapeHandle, Compound, Cuboid, Ball};
+use ncollide::shape::Cuboid;
 use nphysics3d::object::RigidBody;
 
 // Flight
-use flight::{UberMesh, Error};
+use flight::Error;
 use flight::vr::Trackable;
 
 // GFX
 use gfx;
 use app::App;
+use app::state::{self, VersionedState};
 
-use common::{open_object_directory, Common, CommonReply, Meta};
-use common::gurus::interact::{Moveable, GrabbablePhysicsState, ControllerIndex};
+use common::{Common, CommonReply, Meshes, Meta};
+use common::gurus::interact::{Moveable, GrabbablePhysicsState, GrabbableBodyState, ControllerIndex};
+use common::gurus::physics::{BodyParams, BodyState};
+
+const SNOWMAN: &str = "assets/snowman/";
+const SNOW_BLOCK: &str = "assets/snow-block/";
+const BLOCK_PARAMS: BodyParams = BodyParams { density: 100., restitution: 0., friction: 0.8 };
+
+fn spawn_block(params: &BodyParams) -> RigidBody<f32> {
+    let block_shape = Cuboid::new(Vector3::new(0.15, 0.15, 0.3));
+    let mut body = RigidBody::new_dynamic(block_shape, params.density, params.restitution, params.friction);
+    body.set_margin(0.00001);
+    body
+}
 
 pub struct Snowblock(GrabbablePhysicsState);
 
@@ -26,71 +36,123 @@ impl Snowblock {
     fn update<'a, R: gfx::Resources, C: gfx::CommandBuffer<R> + 'static>
         (&'a mut self,
          common: &mut Common<R, C>, yank_speed: f32)
-         -> impl FnOnce(&mut CommonReply<R, C>, &UberMesh<R>) + 'a {
+         -> impl FnOnce(&mut CommonReply<R, C>) + 'a {
         let gp = self.0.update(&mut common.gurus.interact,
                                &mut common.gurus.physics,
                                Isometry3::identity(),
                                yank_speed);
-        move |reply, mesh| {
+        move |reply| {
             let pos = gp(reply);
-            reply.painters.uber.draw(&mut reply.draw_params, na::convert(pos), mesh);
+            reply.painters.uber.draw(&mut reply.draw_params, na::convert(pos), reply.meshes.object(SNOW_BLOCK));
         }
     }
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct SnowflakeState {
+    blocks: Vec<GrabbableBodyState>,
+}
+
+/// The layout before block motion was saved.
+#[derive(Deserialize)]
+struct SnowflakeStateV1 {
     block_locations: Vec<Isometry3<f32>>,
 }
 
-pub struct Snowflakes<R: gfx::Resources> {
+impl VersionedState for SnowflakeState {
+    const VERSION: u32 = 2;
+
+    fn migrate(version: u32, state: Value) -> Result<Value, String> {
+        match version {
+            // Blocks start at rest
+            1 => {
+                let old: SnowflakeStateV1 = serde_json::from_value(state).map_err(|e| e.to_string())?;
+                let blocks = old.block_locations.into_iter()
+                    .map(|l| GrabbableBodyState {
+                        params: BLOCK_PARAMS,
+                        body: BodyState::at(l),
+                        mov: Moveable::Free,
+                    })
+                    .collect();
+                serde_json::to_value(SnowflakeState { blocks }).map_err(|e| e.to_string())
+            },
+            _ => state::migrate_unversioned(version, state),
+        }
+    }
+}
+
+pub struct Snowflakes {
     blocks: Vec<Snowblock>,
     new_blocks: Vec<Snowblock>,
     remove_blocks: Vec<usize>,
-    snowman: UberMesh<R>,
-    snow_block: UberMesh<R>,
 }
 
-impl<R: gfx::Resources> Snowflakes<R> {
-    pub fn new<F: gfx::Factory<R>>(factory: &mut F) -> Result<Self, Error> {
+impl Snowflakes {
+    /// Let go of every block and stop it in place.
+    fn freeze(&mut self) {
+        for block in self.blocks.iter_mut().chain(self.new_blocks.iter_mut()) {
+            block.0.mov = Moveable::Free;
+            block.0.body.set_lin_vel(na::zero());
+            block.0.body.set_ang_vel(na::zero());
+        }
+    }
+
+    pub fn new<R, F>(factory: &mut F, meshes: &mut Meshes<R>) -> Result<Self, Error>
+        where R: gfx::Resources, F: gfx::Factory<R>
+    {
+        meshes.load_object(factory, SNOWMAN)?;
+        meshes.load_object(factory, SNOW_BLOCK)?;
+        meshes.require_shape(SNOWMAN)?;
         Ok(Snowflakes {
             blocks: Vec::new(),
             new_blocks: Vec::new(),
             remove_blocks: Vec::new(),
-            snowman: open_object_directory(factory, "assets/snowman/")?,
-            snow_block: open_object_directory(factory, "assets/snow-block/")?,
         })
     }
 }
 
-impl<R: gfx::Resources + 'static, C: gfx::CommandBuffer<R> + 'static, W: Write, Re: Read> App<R, C, W, Re>
-    for Snowflakes<R> {
-    fn se_state(&self,
-                serializer: &mut Serializer<W>, _: &mut Meta)
-                -> Result<<&mut Serializer<W> as serde::Serializer>::Ok, JsonError> {
-        let block_locations = self.blocks.iter().map(|b| *b.0.body.position()).collect();
-        let state = SnowflakeState { block_locations: block_locations };
-        state.serialize(serializer)
+impl<R: gfx::Resources + 'static, C: gfx::CommandBuffer<R> + 'static> App<R, C> for Snowflakes {
+    fn se_state(&self, _: &mut Meta) -> Result<Value, JsonError> {
+        // Blocks spawned this frame are saved too
+        let blocks = self.blocks.iter()
+            .chain(self.new_blocks.iter())
+            .map(|b| b.0.save(BLOCK_PARAMS))
+            .collect();
+        state::save(&SnowflakeState { blocks })
     }
 
-    fn de_state(&mut self, deserializer: &mut Deserializer<JsonRead<Re>>, _: &mut Meta) -> Result<(), JsonError> {
+    fn de_state(&mut self, saved: Value, _: &mut Meta) -> Result<(), JsonError> {
+        // Read in the new blocks
+        let state: SnowflakeState = state::load(saved)?;
+
         // Clear all of the current state
         self.blocks.clear();
-
-        // Read in the new block locations
-        let state = SnowflakeState::deserialize(deserializer)?;
-        self.new_blocks = state.block_locations.iter().map(|l| {
-            let block_shape = Cuboid::new(Vector3::new(0.15, 0.15, 0.3));
-            let mut body = RigidBody::new_dynamic(block_shape, 100., 0.0, 0.8);
-            body.set_transformation(*l);
-            Snowblock(GrabbablePhysicsState::new_free(body))
+        self.new_blocks = state.blocks.iter().map(|b| {
+            let mut block = GrabbablePhysicsState::new_free(spawn_block(&b.params));
+            block.restore(b);
+            Snowblock(block)
         }).collect();
         Ok(())
     }
 
+    fn on_deactivate(&mut self, _: &mut Meta) {
+        self.freeze();
+    }
+
+    fn on_suspend(&mut self, _: &mut Meta) {
+        self.freeze();
+    }
+
     fn update<'b>(&'b mut self,
                   common: &mut Common<R, C>)
                   -> Box<FnBox(&mut CommonReply<R, C>) + 'b> {
+        if common.gurus.menu.entry("snowflakes/clear", "Clear snow blocks") {
+            self.blocks.clear();
+            self.new_blocks.clear();
+            self.remove_blocks.clear();
+            common.gurus.history.change("clear snow blocks");
+        }
+
         // Add the old blocks
         self.blocks.append(&mut self.new_blocks);
 
@@ -99,24 +161,26 @@ impl<R: gfx::Resources + 'static, C: gfx::CommandBuffer<R> + 'static, W: Write,
             for remove in self.remove_blocks.iter() {
                 self.blocks.remove(*remove);
             }
+            if !self.remove_blocks.is_empty() {
+                common.gurus.history.change("remove snow block");
+            }
             self.remove_blocks.clear();
         }
 
-        // Snowmen
-        let snowman_shapes = vec![
-            (Isometry3::new(Vector3::new(0., 0.22, 0.), na::zero()), ShapeHandle::new(Ball::new(0.26))),
-            (Isometry3::new(Vector3::new(0., 0.60, 0.), na::zero()), ShapeHandle::new(Ball::new(0.20))),
-            (Isometry3::new(Vector3::new(0., 0.85, 0.), na::zero()), ShapeHandle::new(Ball::new(0.15))),
-        ];
-        let snowman_shape = Compound::new(snowman_shapes);
+        // Snowmen, shaped by whichever collision.json was loaded last
+        let snowman_shape = common.meshes.shape(SNOWMAN).cloned();
         let snowmen_locations = vec![Translation3::new(2., 0., 2.),
                                      Translation3::new(-2., 0., 2.),
                                      Translation3::new(-2., 0., -2.),
                                      Translation3::new(2., 0., -2.)];
 
         let block_spawns = snowmen_locations.into_iter().flat_map(|loc| {
-            common.painters.uber.draw(&mut common.draw_params, na::convert(loc), &self.snowman);
-            let mut body = RigidBody::new_static(snowman_shape.clone(), 0.0, 0.8);
+            common.painters.uber.draw(&mut common.draw_params, na::convert(loc), common.meshes.object(SNOWMAN));
+            let shape = match snowman_shape {
+                Some(ref s) => s,
+                None => return Vec::new(),
+            };
+            let mut body = RigidBody::new_static(shape.clone(), 0.0, 0.8);
             body.set_translation(loc);
             common.gurus.physics.body(body);
 
@@ -127,7 +191,7 @@ impl<R: gfx::Resources + 'static, C: gfx::CommandBuffer<R> + 'static, W: Write,
                         index,
                         con.pointing_laser(
                             &Isometry3::from_parts(loc, na::one()),
-                            &snowman_shape,
+                            shape,
                             true),
                     )) } else { None }
                 }).collect::<Vec<_>>()
@@ -144,22 +208,20 @@ impl<R: gfx::Resources + 'static, C: gfx::CommandBuffer<R> + 'static, W: Write,
                 if s.0.body.position().translation.vector.y < -10. {
                     remove_blocks.push(i);
                 }
-                let yank_speed = 0.2 / common.meta.physics_speed;
+                let yank_speed = 0.2 / common.meta.physics_speed();
                 s.update(common, yank_speed)
             })
             .collect();
 
         // Render snow blocks
-        let snow_block = &self.snow_block;
         let new_blocks = &mut self.new_blocks;
         Box::new(move |r: &mut CommonReply<R, C>| {
+            let spawned = new_blocks.len();
             new_blocks.extend(block_spawns.into_iter()
                 .filter_map(|(i, f)| f(&r.reply.interact).map(|h| (i, h)))
                 .map(|(index, hit)| {
                     let con = index.reply(&r.reply.interact);
-                    let block_shape = Cuboid::new(Vector3::new(0.15, 0.15, 0.3));
-                    let mut body = RigidBody::new_dynamic(block_shape, 100., 0.0, 0.8);
-                    body.set_margin(0.00001);
+                    let mut body = spawn_block(&BLOCK_PARAMS);
                     body.set_translation(Translation3::from_vector(
                         con.data.origin().coords + con.data.pointing() * hit.toi
                     ));
@@ -171,8 +233,11 @@ impl<R: gfx::Resources + 'static, C: gfx::CommandBuffer<R> + 'static, W: Write,
                         },
                     })
                 }));
+            if new_blocks.len() > spawned {
+                r.reply.history.change("spawn snow block");
+            }
             for block in futures {
-                block(r, snow_block);
+                block(r);
             }
         })
     }
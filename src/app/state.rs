@@ -0,0 +1,287 @@
+//! Versioned app states, and the formats they are written in.
+//!
+//! States are saved as `{"version": N, "state": ...}`. When an older version is
+//! loaded, it is upgraded one version at a time by `VersionedState::migrate`
+//! before being deserialized. Files saved before states were versioned hold
+//! the state alone, and are treated as version 0.
+
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use serde::de::{self, DeserializeOwned};
+use serde_json::{self, Value, Error};
+use serde_cbor;
+use ron;
+
+use super::autosave::backup_path;
+
+/// A state that can be upgraded from the layouts it had in the past.
+pub trait VersionedState: Serialize + DeserializeOwned {
+    /// The version written when saving.
+    const VERSION: u32;
+
+    /// Upgrade a state from `version` to `version + 1`. Overrides should
+    /// fall back to `migrate_unversioned`.
+    fn migrate(version: u32, state: Value) -> Result<Value, String> {
+        migrate_unversioned(version, state)
+    }
+}
+
+/// Upgrade an unversioned state, which has the same layout as version 1.
+pub fn migrate_unversioned(version: u32, state: Value) -> Result<Value, String> {
+    match version {
+        0 => Ok(state),
+        _ => Err(format!("no migration from version {}", version)),
+    }
+}
+
+#[derive(Serialize)]
+struct Envelope<'a, T: 'a> {
+    version: u32,
+    state: &'a T,
+}
+
+/// Save a state along with its version.
+pub fn save<T: VersionedState>(state: &T) -> Result<Value, Error> {
+    serde_json::to_value(Envelope { version: T::VERSION, state })
+}
+
+/// Split a saved value into its version and state.
+fn open_envelope(value: Value) -> (u32, Value) {
+    match value {
+        Value::Object(mut map) => {
+            let versioned = map.len() == 2
+                && map.contains_key("state")
+                && map.get("version").and_then(Value::as_u64).is_some();
+            if versioned {
+                let version = map["version"].as_u64().unwrap() as u32;
+                (version, map.remove("state").unwrap())
+            } else {
+                (0, Value::Object(map))
+            }
+        },
+        other => (0, other),
+    }
+}
+
+/// Load a state, upgrading it if it was saved by an older version.
+pub fn load<T: VersionedState>(value: Value) -> Result<T, Error> {
+    let (mut version, mut state) = open_envelope(value);
+    if version > T::VERSION {
+        return Err(de::Error::custom(format!(
+            "state is version {}, newer than the supported version {}",
+            version,
+            T::VERSION,
+        )));
+    }
+    while version < T::VERSION {
+        state = T::migrate(version, state).map_err(<Error as de::Error>::custom)?;
+        version += 1;
+    }
+    serde_json::from_value(state)
+}
+
+/// Load a state saved at `base` (without an extension) by passing it to
+/// `apply`. If the latest save in `format` can't be applied its backups are
+/// tried, then saves in the other formats. `what` names the state in logs.
+///
+/// Returns false if nothing could be loaded. When there were saves but none
+/// could be loaded, the newest is kept with an `.invalid` extension.
+pub fn load_saved<F>(what: &str, base: &Path, format: StateFormat, backups: usize, mut apply: F) -> bool
+    where F: FnMut(Value) -> Result<(), String>
+{
+    let primary = format.path(base);
+    let mut candidates: Vec<(StateFormat, PathBuf)> = Vec::new();
+    let others = StateFormat::ALL.iter().cloned().filter(|&f| f != format);
+    for f in Some(format).into_iter().chain(others) {
+        let path = f.path(base);
+        for n in 0..backups + 1 {
+            let candidate = if n == 0 { path.clone() } else { backup_path(&path, n) };
+            if candidate.is_file() && !candidates.iter().any(|c| c.1 == candidate) {
+                candidates.push((f, candidate));
+            }
+        }
+    }
+    if candidates.is_empty() {
+        return false;
+    }
+
+    for &(f, ref path) in &candidates {
+        let result = read_file(path)
+            .and_then(|data| f.decode(&data))
+            .and_then(|value| apply(value));
+        match result {
+            Ok(()) => {
+                if *path != primary {
+                    event!(Warn, "state_load_fallback", what = what, path = path);
+                }
+                return true;
+            },
+            Err(e) => event!(Warn, "state_load_failed", what = what, path = path, error = e),
+        }
+    }
+
+    // Keep the old file around, since it is overwritten by saves
+    let mut kept = OsString::from(&candidates[0].1);
+    kept.push(".invalid");
+    let kept = PathBuf::from(kept);
+    event!(Warn, "state_reset", what = what, kept = kept);
+    if let Err(e) = fs::copy(&candidates[0].1, &kept) {
+        warn!("Could not keep old save: {}", e);
+    }
+    false
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>, String> {
+    let mut data = Vec::new();
+    File::open(path)
+        .and_then(|mut f| f.read_to_end(&mut data))
+        .map_err(|e| e.to_string())?;
+    Ok(data)
+}
+
+/// How states are written to disk. Named `json`, `pretty-json`, `cbor` or
+/// `ron` in the config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StateFormat {
+    #[serde(rename = "json")]
+    Json,
+    #[serde(rename = "pretty-json")]
+    PrettyJson,
+    #[serde(rename = "cbor")]
+    Cbor,
+    #[serde(rename = "ron")]
+    Ron,
+}
+
+impl StateFormat {
+    /// Every format, tried in this order when looking for a state in a
+    /// different format than the current one.
+    pub const ALL: [StateFormat; 4] = [
+        StateFormat::PrettyJson,
+        StateFormat::Json,
+        StateFormat::Cbor,
+        StateFormat::Ron,
+    ];
+
+    pub fn extension(&self) -> &'static str {
+        match *self {
+            StateFormat::Json | StateFormat::PrettyJson => "json",
+            StateFormat::Cbor => "cbor",
+            StateFormat::Ron => "ron",
+        }
+    }
+
+    /// Where a state is saved in this format, given its path without an
+    /// extension.
+    pub fn path<P: AsRef<Path>>(&self, base: P) -> PathBuf {
+        let mut name = OsString::from(base.as_ref());
+        name.push(".");
+        name.push(self.extension());
+        PathBuf::from(name)
+    }
+
+    pub fn encode(&self, state: &Value) -> Result<Vec<u8>, String> {
+        match *self {
+            StateFormat::Json => serde_json::to_vec(state).map_err(|e| e.to_string()),
+            StateFormat::PrettyJson => serde_json::to_vec_pretty(state).map_err(|e| e.to_string()),
+            StateFormat::Cbor => serde_cbor::to_vec(state).map_err(|e| e.to_string()),
+            StateFormat::Ron => ron::ser::to_string_pretty(state, Default::default())
+                .map(String::into_bytes)
+                .map_err(|e| e.to_string()),
+        }
+    }
+
+    pub fn decode(&self, data: &[u8]) -> Result<Value, String> {
+        match *self {
+            StateFormat::Json | StateFormat::PrettyJson =>
+                serde_json::from_slice(data).map_err(|e| e.to_string()),
+            StateFormat::Cbor => serde_cbor::from_slice(data).map_err(|e| e.to_string()),
+            StateFormat::Ron => {
+                let text = String::from_utf8(data.to_vec()).map_err(|e| e.to_string())?;
+                ron::de::from_str(&text).map_err(|e| e.to_string())
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(text: &str) -> Value {
+        serde_json::from_str(text).unwrap()
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Counter {
+        count: u64,
+    }
+
+    impl VersionedState for Counter {
+        const VERSION: u32 = 3;
+
+        fn migrate(version: u32, state: Value) -> Result<Value, String> {
+            match version {
+                // `n` was renamed to `count`
+                1 => Ok(value(&format!("{{\"count\": {}}}", state["n"]))),
+                // Counts were doubled
+                2 => match state["count"].as_u64() {
+                    Some(n) => Ok(value(&format!("{{\"count\": {}}}", n * 2))),
+                    None => Err("no count".to_owned()),
+                },
+                _ => migrate_unversioned(version, state),
+            }
+        }
+    }
+
+    #[test]
+    fn envelope_is_split() {
+        let (version, state) = open_envelope(value(r#"{"version": 2, "state": {"a": 1}}"#));
+        assert_eq!(version, 2);
+        assert_eq!(state, value(r#"{"a": 1}"#));
+    }
+
+    #[test]
+    fn unversioned_states_are_version_zero() {
+        let saved = value(r#"{"version": 2, "state": 1, "other": 3}"#);
+        assert_eq!(open_envelope(saved.clone()), (0, saved));
+
+        let saved = value(r#"{"version": "2", "state": 1}"#);
+        assert_eq!(open_envelope(saved.clone()), (0, saved));
+
+        assert_eq!(open_envelope(value("[1, 2]")), (0, value("[1, 2]")));
+    }
+
+    #[test]
+    fn states_are_migrated_one_version_at_a_time() {
+        let loaded: Counter = load(value(r#"{"n": 2}"#)).unwrap();
+        assert_eq!(loaded, Counter { count: 4 });
+
+        let loaded: Counter = load(value(r#"{"version": 2, "state": {"count": 3}}"#)).unwrap();
+        assert_eq!(loaded, Counter { count: 6 });
+
+        let loaded: Counter = load(value(r#"{"version": 3, "state": {"count": 3}}"#)).unwrap();
+        assert_eq!(loaded, Counter { count: 3 });
+    }
+
+    #[test]
+    fn failed_migrations_are_errors() {
+        assert!(load::<Counter>(value(r#"{"version": 2, "state": {}}"#)).is_err());
+    }
+
+    #[test]
+    fn newer_states_are_rejected() {
+        assert!(load::<Counter>(value(r#"{"version": 4, "state": {"count": 3}}"#)).is_err());
+    }
+
+    #[test]
+    fn saved_states_load_unchanged() {
+        let saved = save(&Counter { count: 5 }).unwrap();
+        assert_eq!(saved["version"], Value::from(3));
+        assert_eq!(load::<Counter>(saved).unwrap(), Counter { count: 5 });
+    }
+}
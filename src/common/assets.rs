@@ -0,0 +1,102 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use std::time::{Duration, Instant, SystemTime};
+
+/// The directory asset paths start with.
+pub const ASSET_DIR: &str = "assets";
+
+lazy_static! {
+    static ref ROOT: RwLock<PathBuf> = RwLock::new(PathBuf::from(ASSET_DIR));
+}
+
+/// Read assets from `root` instead of `ASSET_DIR`, on every thread. Asset
+/// paths are still written starting with `assets/`.
+pub fn set_root<P: Into<PathBuf>>(root: P) {
+    *ROOT.write().unwrap() = root.into();
+}
+
+/// Where an asset is on disk. Paths starting with `assets/` are moved into the
+/// root set by `set_root`. Other paths, and paths already in the root, are
+/// returned unchanged.
+pub fn resolve<P: AsRef<Path>>(path: P) -> PathBuf {
+    let path = path.as_ref();
+    let root = ROOT.read().unwrap();
+    if path.starts_with(&*root) {
+        return path.to_owned();
+    }
+    match path.strip_prefix(ASSET_DIR) {
+        Ok(rest) => root.join(rest),
+        Err(_) => path.to_owned(),
+    }
+}
+
+/// The time a file was last modified, or the latest time any file directly
+/// inside a directory was modified.
+fn stamp(path: &Path) -> Option<SystemTime> {
+    let meta = match fs::metadata(path) {
+        Ok(m) => m,
+        Err(_) => return None,
+    };
+    if meta.is_dir() {
+        let entries = match fs::read_dir(path) {
+            Ok(e) => e,
+            Err(_) => return None,
+        };
+        entries
+            .filter_map(|e| e.ok())
+            .filter_map(|e| e.metadata().ok())
+            .filter(|m| m.is_file())
+            .filter_map(|m| m.modified().ok())
+            .max()
+    } else {
+        meta.modified().ok()
+    }
+}
+
+/// Polls asset files and directories for changes, so that they can be
+/// reloaded while the shell is running.
+pub struct AssetWatcher {
+    watched: HashMap<PathBuf, Option<SystemTime>>,
+    /// Time between checks.
+    pub interval: Duration,
+    last_check: Instant,
+}
+
+impl AssetWatcher {
+    pub fn new(interval: Duration) -> AssetWatcher {
+        AssetWatcher {
+            watched: HashMap::new(),
+            interval,
+            last_check: Instant::now(),
+        }
+    }
+
+    /// Start watching a file, or every file in a directory.
+    pub fn watch<P: Into<PathBuf>>(&mut self, path: P) {
+        let path = path.into();
+        let current = stamp(&resolve(&path));
+        self.watched.entry(path).or_insert(current);
+    }
+
+    /// Paths that have changed since the last call. Returns nothing until
+    /// `interval` has passed since the last check.
+    pub fn changed(&mut self) -> Vec<PathBuf> {
+        if self.last_check.elapsed() < self.interval {
+            return Vec::new();
+        }
+        self.last_check = Instant::now();
+
+        let mut changed = Vec::new();
+        for (path, last) in self.watched.iter_mut() {
+            let current = stamp(&resolve(path));
+            // A missing file is probably being written, wait for it to return
+            if current.is_some() && current != *last {
+                *last = current;
+                changed.push(path.clone());
+            }
+        }
+        changed
+    }
+}
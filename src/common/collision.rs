@@ -0,0 +1,193 @@
+//! Collision shapes described by data files.
+//!
+//! An object directory may contain a `collision.json` holding a `ShapeDesc`.
+//! Shapes are always built as compounds, so that they can be used for both
+//! static and dynamic rigid bodies. Triangle meshes can only be used for static
+//! bodies, dynamic bodies should use a convex decomposition instead.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::Arc;
+
+use serde_json;
+use nalgebra::{self as na, Vector3, Point3, Isometry3};
+use ncollide::shape::{ShapeHandle, Compound, ConvexHull, Cuboid, Cylinder, Ball, TriMesh};
+use ncollide::procedural::{self, IndexBuffer};
+use ncollide::transformation;
+
+use flight::{Error, load};
+use flight::mesh::Indexing;
+
+use super::assets::resolve;
+
+/// The shape of any object loaded from a description.
+pub type CollisionShape = Compound<Point3<f32>, Isometry3<f32>>;
+
+fn zero() -> Vector3<f32> { na::zero() }
+fn default_error() -> f32 { 0.03 }
+fn default_components() -> usize { 1 }
+
+/// A collision shape.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ShapeDesc {
+    Ball { radius: f32 },
+    Cuboid { half_extents: Vector3<f32> },
+    Cylinder { half_height: f32, radius: f32 },
+    Compound { parts: Vec<PartDesc> },
+    /// The convex hull of the `model.obj` in the object directory.
+    ConvexHull,
+    /// The triangles of the `model.obj` in the object directory. Only for
+    /// static bodies.
+    Trimesh,
+    /// Convex pieces approximating the `model.obj` in the object directory.
+    ConvexDecomposition {
+        /// How far the pieces may stray from the model, relative to its size.
+        #[serde(default = "default_error")]
+        error: f32,
+        /// Stop merging pieces once there are this many.
+        #[serde(default = "default_components")]
+        min_components: usize,
+    },
+}
+
+/// A shape placed inside of a compound shape.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PartDesc {
+    #[serde(default = "zero")]
+    pub position: Vector3<f32>,
+    /// Axis of rotation, scaled by the angle in radians.
+    #[serde(default = "zero")]
+    pub rotation: Vector3<f32>,
+    pub shape: ShapeDesc,
+}
+
+/// Every vertex position in a wavefront file.
+pub fn model_points<P: AsRef<Path>>(path: P) -> Result<Vec<Point3<f32>>, Error> {
+    Ok(load::open_wavefront(resolve(path))?.verts.iter()
+        .map(|v| Point3::new(v.pos[0], v.pos[1], v.pos[2]))
+        .collect())
+}
+
+/// The triangles of a wavefront file. Vertices in the same place are merged,
+/// so that neighboring triangles share them.
+pub fn model_triangles<P: AsRef<Path>>(path: P)
+    -> Result<(Vec<Point3<f32>>, Vec<Point3<usize>>), Error>
+{
+    let source = load::open_wavefront(resolve(path))?;
+    let corners: Vec<usize> = match source.inds {
+        Indexing::Inds(ref inds) => inds.iter().map(|&i| i as usize).collect(),
+        _ => (0..source.verts.len()).collect(),
+    };
+
+    let mut points = Vec::new();
+    let mut welded = HashMap::new();
+    let mut remap = Vec::with_capacity(source.verts.len());
+    for v in &source.verts {
+        let key = [v.pos[0].to_bits(), v.pos[1].to_bits(), v.pos[2].to_bits()];
+        let index = *welded.entry(key).or_insert_with(|| {
+            points.push(Point3::new(v.pos[0], v.pos[1], v.pos[2]));
+            points.len() - 1
+        });
+        remap.push(index);
+    }
+
+    let triangles = corners.chunks(3)
+        .filter(|c| c.len() == 3)
+        .map(|c| Point3::new(remap[c[0]], remap[c[1]], remap[c[2]]))
+        .collect();
+    Ok((points, triangles))
+}
+
+impl ShapeDesc {
+    /// Build the shape, scaled uniformly. `dir` is the object directory that
+    /// generated shapes are built from.
+    pub fn handle(&self, dir: &Path, scale: f32)
+        -> Result<ShapeHandle<Point3<f32>, Isometry3<f32>>, Error>
+    {
+        Ok(match *self {
+            ShapeDesc::Ball { radius } =>
+                ShapeHandle::new(Ball::new(radius * scale)),
+            ShapeDesc::Cuboid { half_extents } =>
+                ShapeHandle::new(Cuboid::new(half_extents * scale)),
+            ShapeDesc::Cylinder { half_height, radius } =>
+                ShapeHandle::new(Cylinder::new(half_height * scale, radius * scale)),
+            ShapeDesc::Compound { .. } =>
+                ShapeHandle::new(self.compound(dir, scale)?),
+            ShapeDesc::ConvexHull => {
+                let points = model_points(dir.join("model.obj"))?;
+                ShapeHandle::new(ConvexHull::new(points.into_iter().map(|p| p * scale).collect()))
+            },
+            ShapeDesc::Trimesh => {
+                let (points, triangles) = model_triangles(dir.join("model.obj"))?;
+                ShapeHandle::new(TriMesh::new(
+                    Arc::new(points.into_iter().map(|p| p * scale).collect()),
+                    Arc::new(triangles),
+                    None,
+                    None,
+                ))
+            },
+            ShapeDesc::ConvexDecomposition { .. } =>
+                ShapeHandle::new(self.compound(dir, scale)?),
+        })
+    }
+
+    /// If this shape can only be used for static bodies.
+    pub fn is_static_only(&self) -> bool {
+        match *self {
+            ShapeDesc::Trimesh => true,
+            ShapeDesc::Compound { ref parts } => parts.iter().any(|p| p.shape.is_static_only()),
+            _ => false,
+        }
+    }
+
+    /// Build the shape as a compound.
+    pub fn compound(&self, dir: &Path, scale: f32) -> Result<CollisionShape, Error> {
+        Ok(match *self {
+            ShapeDesc::Compound { ref parts } => {
+                let mut shapes = Vec::with_capacity(parts.len());
+                for p in parts {
+                    let pos = Isometry3::new(p.position * scale, p.rotation);
+                    shapes.push((pos, p.shape.handle(dir, scale)?));
+                }
+                Compound::new(shapes)
+            },
+            ShapeDesc::ConvexDecomposition { error, min_components } => {
+                let (points, triangles) = model_triangles(dir.join("model.obj"))?;
+                let mut mesh = procedural::TriMesh::new(
+                    points.into_iter().map(|p| p * scale).collect(),
+                    None,
+                    None,
+                    Some(IndexBuffer::Unified(triangles.into_iter()
+                        .map(|t| Point3::new(t.x as u32, t.y as u32, t.z as u32))
+                        .collect())),
+                );
+                mesh.recompute_normals();
+                let (pieces, _) = transformation::hacd(mesh, error, min_components);
+                Compound::new(pieces.into_iter()
+                    .map(|p| (Isometry3::identity(), ShapeHandle::new(ConvexHull::new(p.coords))))
+                    .collect())
+            },
+            _ => Compound::new(vec![(Isometry3::identity(), self.handle(dir, scale)?)]),
+        })
+    }
+}
+
+/// Read the `collision.json` in an object directory, if there is one. Invalid
+/// files are skipped with a warning.
+pub fn open_collision<P: AsRef<Path>>(dir: P) -> Option<ShapeDesc> {
+    let path = resolve(dir).join("collision.json");
+    let file = match File::open(&path) {
+        Ok(f) => f,
+        Err(_) => return None,
+    };
+    match serde_json::from_reader(BufReader::new(file)) {
+        Ok(desc) => Some(desc),
+        Err(e) => {
+            warn!("Could not read {}: {}", path.display(), e);
+            None
+        },
+    }
+}
@@ -0,0 +1,121 @@
+//! Environment maps, read from disk in the background.
+//!
+//! Reading every cubemap face of an environment takes long enough to stall
+//! the headset, so the files are read on a worker thread by
+//! `EnvironmentLoader`. Only decoding and uploading them, in
+//! `open_environment`, happens on the render thread.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Cursor, Read};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use gfx;
+
+use flight::{Error, load};
+use flight::draw::{Painter, UberStyle};
+
+use super::assets::resolve;
+use super::manifest::EnvironmentDesc;
+
+/// Names of cubemap faces, as used in environment file names.
+pub const CUBE_SIDES: [&str; 6] = ["posx", "negx", "posy", "negy", "posz", "negz"];
+
+/// The cubemap faces of an environment, read into memory.
+pub struct EnvironmentFiles {
+    pub desc: EnvironmentDesc,
+    /// Contents of each file, by file name.
+    files: HashMap<String, Vec<u8>>,
+}
+
+impl EnvironmentFiles {
+    /// Read every cubemap face of an environment.
+    pub fn read(desc: EnvironmentDesc, dir: &Path) -> io::Result<EnvironmentFiles> {
+        let mut names = Vec::new();
+        for level in 0..desc.radiance_levels {
+            for side in &CUBE_SIDES {
+                names.push(format!("radiance_{}_{}.hdr", level, side));
+            }
+        }
+        for side in &CUBE_SIDES {
+            names.push(format!("irradiance_{}.hdr", side));
+        }
+
+        let mut files = HashMap::new();
+        for name in names {
+            let mut data = Vec::new();
+            File::open(dir.join(&name))?.read_to_end(&mut data)?;
+            files.insert(name, data);
+        }
+        Ok(EnvironmentFiles { desc, files })
+    }
+
+    fn open(&self, name: String) -> io::Result<Cursor<Vec<u8>>> {
+        match self.files.get(&name) {
+            Some(data) => Ok(Cursor::new(data.clone())),
+            None => Err(io::Error::new(io::ErrorKind::NotFound, name)),
+        }
+    }
+}
+
+/// Decode the cubemaps of an environment and load them into the uber painter.
+pub fn open_environment<R, F>(f: &mut F, uber: &mut Painter<R, UberStyle<R>>, env: &EnvironmentFiles)
+    -> Result<(), Error>
+    where R: gfx::Resources, F: gfx::Factory<R>
+{
+    let desc = &env.desc;
+    let radiance = load::load_hdr_cubemap(f, desc.radiance_levels, |side, level| {
+        Ok(env.open(format!("radiance_{}_{}.hdr", level, side))?)
+    })?;
+    let irradiance = load::load_hdr_cubemap(f, 1, |side, _| {
+        Ok(env.open(format!("irradiance_{}.hdr", side))?)
+    })?;
+    uber.cfg(|s| {
+        let e = s.mut_env();
+        e.radiance = radiance;
+        e.radiance_levels = desc.radiance_levels;
+        e.irradiance = irradiance;
+        e.sun_included = desc.sun_included;
+        e.sun_color = desc.sun_color;
+    });
+    Ok(())
+}
+
+/// Reads environments on a worker thread, one at a time.
+pub struct EnvironmentLoader {
+    pending: Option<(String, Receiver<io::Result<EnvironmentFiles>>)>,
+}
+
+impl EnvironmentLoader {
+    pub fn new() -> EnvironmentLoader {
+        EnvironmentLoader { pending: None }
+    }
+
+    /// Start reading an environment. An environment still being read is
+    /// forgotten.
+    pub fn request(&mut self, desc: &EnvironmentDesc) {
+        let (send, recv) = mpsc::channel();
+        let desc = desc.clone();
+        self.pending = Some((desc.name.clone(), recv));
+        thread::spawn(move || {
+            let dir = resolve(&desc.path);
+            let _ = send.send(EnvironmentFiles::read(desc, &dir));
+        });
+    }
+
+    /// The environment that was requested and its files, once they are read.
+    pub fn poll(&mut self) -> Option<(String, io::Result<EnvironmentFiles>)> {
+        let result = match self.pending {
+            Some((_, ref recv)) => match recv.try_recv() {
+                Ok(r) => r,
+                Err(TryRecvError::Empty) => return None,
+                Err(TryRecvError::Disconnected) =>
+                    Err(io::Error::new(io::ErrorKind::Other, "environment reader stopped")),
+            },
+            None => return None,
+        };
+        self.pending.take().map(|(name, _)| (name, result))
+    }
+}
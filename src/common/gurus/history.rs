@@ -0,0 +1,41 @@
+/// Collects the undoable changes apps make during a frame.
+///
+/// Apps report a change when something discrete happens, like an object being
+/// grabbed or a slider being released. After the frame, the shell saves every
+/// app's state as a new point in the undo history.
+pub struct HistoryGuru {
+    changes: Vec<String>,
+}
+
+impl HistoryGuru {
+    pub fn new() -> HistoryGuru {
+        HistoryGuru {
+            changes: Vec::new(),
+        }
+    }
+
+    /// Report a change, described by `label` in the undo menu.
+    pub fn change<L: Into<String>>(&mut self, label: L) {
+        self.changes.push(label.into());
+    }
+
+    /// Complete this guru's calculations.
+    pub fn resolve(self) -> HistoryReply {
+        HistoryReply {
+            changes: self.changes,
+        }
+    }
+}
+
+/// The changes reported during a frame. Changes found while handling replies
+/// are added here.
+pub struct HistoryReply {
+    pub changes: Vec<String>,
+}
+
+impl HistoryReply {
+    /// Report a change, described by `label` in the undo menu.
+    pub fn change<L: Into<String>>(&mut self, label: L) {
+        self.changes.push(label.into());
+    }
+}
@@ -5,7 +5,7 @@ use nphysics3d::object::RigidBody;
 use gfx;
 use flight::vr::{Trackable, MappedController};
 use common::{CommonReply};
-use super::physics::PhysicsGuru;
+use super::physics::{PhysicsGuru, BodyParams, BodyState};
 use std::collections::BinaryHeap;
 use std::cmp::{Ord, PartialOrd, PartialEq, Ordering};
 use std::f32::INFINITY;
@@ -83,7 +83,7 @@ impl PartialOrd for InteractQuery {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
 pub struct ControllerIndex(u8);
 
 impl ControllerIndex {
@@ -284,6 +284,16 @@ pub struct ControllerReply {
     can_touch: bool,
 }
 
+/// A request to vibrate a controller.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct HapticPulse {
+    pub index: ControllerIndex,
+    /// How strong the pulse is, from 0 to 1.
+    pub strength: f32,
+    /// How long the pulse lasts, in seconds.
+    pub duration: f32,
+}
+
 pub const YANK_DIFFICULTY: f32 = 1.0;
 
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
@@ -293,7 +303,7 @@ pub enum MoveableIntention {
     Free,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Moveable {
     Grabbed {
         index: ControllerIndex,
@@ -316,6 +326,9 @@ impl Default for Moveable {
 pub struct MoveData {
     pub intent: MoveableIntention,
     pub fixed: Option<Fixed>,
+    /// The controller that pulled its trigger this frame while pointing at or
+    /// touching the object, if it was free.
+    pub pressed: Option<ControllerIndex>,
 }
 
 #[derive(Debug, Clone)]
@@ -334,6 +347,146 @@ impl Fixed {
 }
 
 impl Moveable {
+    /// The controller holding the object, if any.
+    pub fn held_by(&self) -> Option<ControllerIndex> {
+        match *self {
+            Moveable::Grabbed { index } | Moveable::Yanked { index, .. } => Some(index),
+            Moveable::Free => None,
+        }
+    }
+
+    /// Name of the variant, for logging.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Moveable::Grabbed { .. } => "Grabbed",
+            Moveable::Yanked { .. } => "Yanked",
+            Moveable::Free => "Free",
+        }
+    }
+
+    /// How the object should be saved. A grab can't outlive the session, so
+    /// grabbed objects are saved as free and fall with their current velocity.
+    pub fn saved(&self) -> Moveable {
+        match *self {
+            Moveable::Grabbed { .. } => Moveable::Free,
+            ref m => m.clone(),
+        }
+    }
+
+    /// Advance a held object given the state of the holding controller. Free
+    /// objects are unchanged, since picking them up depends on resolved
+    /// pointing and touching.
+    fn step(&self, con: &MappedController, d_yank: f32) -> Moveable {
+        use self::Moveable::*;
+
+        match *self {
+            Yanked { progress, index } => {
+                if progress + d_yank > 1. && !con.menu {
+                    Free
+                } else {
+                    Yanked {
+                        progress: (progress + d_yank).min(1.),
+                        index: index,
+                    }
+                }
+            },
+            Grabbed { index } => {
+                if con.trigger < 0.5 {
+                    Free
+                } else {
+                    Grabbed { index: index }
+                }
+            },
+            Free => Free,
+        }
+    }
+
+    /// Where a held object at `pos` is carried by the holding controller.
+    fn carry(
+        &self,
+        con: &MappedController,
+        pos: Isometry3<f32>,
+        inv_yank_offset: Isometry3<f32>,
+        d_yank: f32,
+    )
+        -> Option<(MoveableIntention, Fixed)>
+    {
+        use self::Moveable::*;
+        use self::MoveableIntention as Mi;
+
+        match *self {
+            Grabbed { index } => {
+                let inv_offset = con.pose.inverse() * pos; // TODO: Do we have to invert?
+                let ang_vel = con.ang_vel;
+                let mut lin_vel = con.lin_vel;
+                lin_vel += ang_vel.cross(&inv_offset.translation.vector);
+
+                Some((Mi::Manipulate, Fixed {
+                    by: index,
+                    pos: con.pose_delta * pos,
+                    inv_offset: inv_offset,
+                    lin_vel: lin_vel,
+                    ang_vel: ang_vel,
+                }))
+            },
+            Yanked { progress, index } => {
+                let (next, lin_vel, ang_vel) = if progress < 1. {
+                    let mut dp = d_yank / (1. - progress + d_yank);
+                    dp = dp.min(1.).max(0.);
+                    let dest = con.pose() * inv_yank_offset;
+                    let next = Isometry3::from_parts(
+                        Translation3::from_vector(
+                            pos.translation.vector * (1. - dp) + dest.translation.vector * dp
+                        ),
+                        pos.rotation.slerp(&dest.rotation, dp),
+                    );
+                    let lin_vel = (next.translation.vector - pos.translation.vector) / con.dt as f32;
+                    let ang_vel = (next.rotation * pos.rotation.inverse()).scaled_axis() / con.dt as f32;
+                    (next, lin_vel, ang_vel)
+                } else {
+                    let ang_vel = con.ang_vel;
+                    let mut lin_vel = con.lin_vel;
+                    lin_vel += ang_vel.cross(&inv_yank_offset.translation.vector);
+                    (con.pose() * inv_yank_offset, lin_vel, ang_vel)
+                };
+
+                Some((Mi::Move, Fixed {
+                    by: index,
+                    pos: next,
+                    inv_offset: con.pose.inverse() * pos,
+                    lin_vel: lin_vel,
+                    ang_vel: ang_vel,
+                }))
+            },
+            Free => None,
+        }
+    }
+
+    /// Where an object that is already held will be carried this frame. This
+    /// matches the `fixed` pose from `update`, but is known before the guru is
+    /// resolved. Objects picked up this frame are not predicted.
+    pub fn predict(
+        &self,
+        interact: &mut InteractGuru,
+        pos: Isometry3<f32>,
+        inv_yank_offset: Isometry3<f32>,
+        yank_speed: f32,
+    )
+        -> Option<Fixed>
+    {
+        let d_yank = interact.dt as f32 / yank_speed;
+        let index = match self.held_by() {
+            Some(i) => i,
+            None => return None,
+        };
+        let con = &index.guru(interact).data;
+        self.step(con, d_yank)
+            .carry(con, pos, inv_yank_offset, d_yank)
+            .map(|(_, fixed)| fixed)
+    }
+
+    /// Update an object that can be yanked when `yankable` is true, or grabbed
+    /// by touching it and pulling the trigger when `grabbable` is true.
     pub fn update<'a>(
         &'a mut self,
         interact: &mut InteractGuru,
@@ -341,6 +494,8 @@ impl Moveable {
         shape: &Shape<Point3<f32>, Isometry3<f32>>,
         inv_yank_offset: Isometry3<f32>,
         yank_speed: f32,
+        grabbable: bool,
+        yankable: bool,
     )
         -> impl FnOnce(&InteractionReply)
         -> MoveData + 'a
@@ -348,9 +503,9 @@ impl Moveable {
         use self::Moveable::*;
         use self::MoveableIntention as Mi;
 
-        let solid = match self {
-            &mut Free => true,
-            &mut Yanked { index, ..} | &mut Grabbed { index, .. } => {
+        let solid = match self.held_by() {
+            None => true,
+            Some(index) => {
                 index.guru(interact).block();
                 false
             },
@@ -365,21 +520,31 @@ impl Moveable {
         let d_yank = interact.dt as f32 / yank_speed;
 
         move |reply| {
-            match self {
-                &mut Free => {
+            let before = self.name();
+            let yanking = match *self {
+                Yanked { progress, .. } => progress < 1.,
+                _ => false,
+            };
+            let mut pressed = None;
+            match self.held_by() {
+                None => {
                     for (ind, pointed, touched) in cons
                         .into_iter()
                         .map(|(i, p, t)| (i, p(reply), t(reply)))
                     {
                         let con = ind.reply(reply);
-                        if let (Some(_), true) = (pointed, con.data.menu) {
+                        if let (Some(_), true, true) = (pointed, con.data.menu, yankable) {
                             *self = Yanked {
                                 index: ind,
                                 progress: 0.,
                             };
                             break
                         }
-                        if con.data.trigger > 0.5 {
+                        let pulled = con.data.trigger > 0.5 && con.data.trigger - con.data.trigger_delta < 0.5;
+                        if pulled && (pointed.is_some() || touched) {
+                            pressed = Some(ind);
+                        }
+                        if grabbable && con.data.trigger > 0.5 {
                             if touched {
                                 *self = Grabbed {
                                     index: ind,
@@ -389,80 +554,31 @@ impl Moveable {
                         }
                     }
                 },
-                &mut Yanked { progress, index } => {
-                    if progress + d_yank > 1. && !index.reply(reply).data.menu {
-                        *self = Free;
-                    } else {
-                        *self = Yanked {
-                            progress: (progress + d_yank).min(1.),
-                            index: index,
-                        };
-                    }
-                },
-                &mut Grabbed { index, .. } => {
-                    if index.reply(reply).data.trigger < 0.5 {
-                        *self = Free;
-                    }
+                Some(index) => {
+                    *self = self.step(&index.reply(reply).data, d_yank);
                 },
             };
-            match self {
-                &mut Grabbed { index } => {
-                    let con = index.reply(reply);
-                    let inv_offset = con.data.pose.inverse() * pos; // TODO: Do we have to invert?
-                    let ang_vel = con.data.ang_vel;
-                    let mut lin_vel = con.data.lin_vel;
-                    lin_vel += ang_vel.cross(&inv_offset.translation.vector);
-
-                    return MoveData {
-                        intent: Mi::Manipulate,
-                        fixed: Some(Fixed {
-                            by: index,
-                            pos: con.data.pose_delta * pos,
-                            inv_offset: inv_offset,
-                            lin_vel: lin_vel,
-                            ang_vel: ang_vel,
-                        }),
-                    };
-                },
-                &mut Yanked { progress, index, .. } => {
-                    let con = index.reply(reply);
-
-                    let (next, lin_vel, ang_vel) = if progress < 1. {
-                        let mut dp = d_yank / (1. - progress + d_yank);
-                        dp = dp.min(1.).max(0.);
-                        let dest = con.data.pose() * inv_yank_offset;
-                        let next = Isometry3::from_parts(
-                            Translation3::from_vector(
-                                pos.translation.vector * (1. - dp) + dest.translation.vector * dp
-                            ),
-                            pos.rotation.slerp(&dest.rotation, dp),
-                        );
-                        let lin_vel = (next.translation.vector - pos.translation.vector) / con.data.dt as f32;
-                        let ang_vel = (next.rotation * pos.rotation.inverse()).scaled_axis() / con.data.dt as f32;
-                        (next, lin_vel, ang_vel)
-                    } else {
-                        let ang_vel = con.data.ang_vel;
-                        let mut lin_vel = con.data.lin_vel;
-                        lin_vel += ang_vel.cross(&inv_yank_offset.translation.vector);
-                        (con.data.pose() * inv_yank_offset, lin_vel, ang_vel)
-                    };
-
-                    return MoveData {
-                        intent: Mi::Move,
-                        fixed: Some(Fixed {
-                            by: index,
-                            pos: next,
-                            inv_offset: con.data.pose.inverse() * pos,
-                            lin_vel: lin_vel,
-                            ang_vel: ang_vel,
-                        }),
-                    };
-                },
+            match *self {
+                Yanked { progress, index } if yanking && progress >= 1. =>
+                    event!(Debug, "yank_complete", controller = index, position = pos.translation.vector),
                 _ => (),
             }
-            MoveData {
-                intent: Mi::Free,
-                fixed: None,
+            if self.name() != before {
+                event!(Debug, "moveable", from = before, to = self.name(), position = pos.translation.vector);
+            }
+            let carried = self.held_by()
+                .and_then(|index| self.carry(&index.reply(reply).data, pos, inv_yank_offset, d_yank));
+            match carried {
+                Some((intent, fixed)) => MoveData {
+                    intent: intent,
+                    fixed: Some(fixed),
+                    pressed,
+                },
+                None => MoveData {
+                    intent: Mi::Free,
+                    fixed: None,
+                    pressed,
+                },
             }
         }
     }
@@ -544,11 +660,36 @@ pub struct GrabbablePhysicsState {
     pub body: RigidBody<f32>,
 }
 
+/// Everything needed to rebuild a `GrabbablePhysicsState`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GrabbableBodyState {
+    pub params: BodyParams,
+    pub body: BodyState,
+    #[serde(default)]
+    pub mov: Moveable,
+}
+
 impl GrabbablePhysicsState {
     pub fn new_free(body: RigidBody<f32>) -> Self {
         GrabbablePhysicsState { mov: Default::default(), body }
     }
 
+    /// Save the motion of the body and who is yanking it. `params` should be
+    /// the material the body was built with.
+    pub fn save(&self, params: BodyParams) -> GrabbableBodyState {
+        GrabbableBodyState {
+            params,
+            body: BodyState::new(&self.body),
+            mov: self.mov.saved(),
+        }
+    }
+
+    /// Restore a saved state onto an existing body, keeping its material.
+    pub fn restore(&mut self, state: &GrabbableBodyState) {
+        state.body.apply(&mut self.body);
+        self.mov = state.mov.clone();
+    }
+
     pub fn update<'a, R: gfx::Resources, C: gfx::CommandBuffer<R>>(
         &'a mut self,
         interact: &mut InteractGuru,
@@ -559,6 +700,10 @@ impl GrabbablePhysicsState {
         -> impl FnOnce(&mut CommonReply<R, C>)
         -> Isometry3<f32> + 'a
     {
+        let was_held = match self.mov {
+            Moveable::Free => false,
+            _ => true,
+        };
         let phys = physics.body(self.body.clone());
         let mov = self.mov.update(
             interact,
@@ -566,12 +711,19 @@ impl GrabbablePhysicsState {
             self.body.shape().as_ref(),
             inv_yank_offset,
             yank_speed,
+            true,
+            true,
         );
 
         let body = &mut self.body;
         move |reply| {
             let mov_data = mov(&reply.reply.interact);
             *body = phys(&reply.reply.physics);
+            match (was_held, mov_data.fixed.is_some()) {
+                (false, true) => reply.reply.history.change("grab"),
+                (true, false) => reply.reply.history.change("release"),
+                _ => (),
+            }
             match mov_data.fixed {
                 Some(Fixed { pos, lin_vel, ang_vel, .. }) => {
                     body.set_transformation(pos);
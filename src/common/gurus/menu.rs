@@ -0,0 +1,47 @@
+/// An item shown in the wrist menu.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MenuEntry {
+    /// Identifies the entry across frames, e.g. `"snowflakes/clear"`.
+    pub id: String,
+    /// Text shown on the entry.
+    pub label: String,
+}
+
+/// Collects the entries apps want in the wrist menu this frame. The wrist
+/// menu reads them directly, so this guru has no reply.
+///
+/// Menu entries are chosen while the wrist menu is being drawn, after all apps
+/// have been updated. Because of this, choices are reported one frame late.
+pub struct MenuGuru {
+    entries: Vec<MenuEntry>,
+    chosen: Vec<String>,
+}
+
+impl MenuGuru {
+    /// Create a new `MenuGuru`, given the ids of the entries chosen on the
+    /// previous frame.
+    pub fn new(chosen: Vec<String>) -> MenuGuru {
+        MenuGuru {
+            entries: Vec::new(),
+            chosen,
+        }
+    }
+
+    /// Show an entry in the wrist menu. Returns `true` if the user chose the
+    /// entry on the previous frame.
+    pub fn entry<I, L>(&mut self, id: I, label: L) -> bool
+        where I: Into<String>, L: Into<String>
+    {
+        let id = id.into();
+        let chosen = self.chosen.iter().any(|c| *c == id);
+        if !self.entries.iter().any(|e| e.id == id) {
+            self.entries.push(MenuEntry { id, label: label.into() });
+        }
+        chosen
+    }
+
+    /// All of the entries registered so far this frame.
+    pub fn entries(&self) -> &[MenuEntry] {
+        &self.entries
+    }
+}
@@ -1,2 +1,4 @@
 pub mod physics;
 pub mod interact;
+pub mod menu;
+pub mod history;
@@ -1,7 +1,8 @@
 use nphysics3d::world::World;
 use nphysics3d::object::RigidBody;
-use nalgebra::{Vector3};
+use nalgebra::{self as na, Vector3, Isometry3};
 use std::rc::Rc;
+use std::time::{Duration, Instant};
 
 pub struct PhysicsGuru {
     world: World<f32>,
@@ -28,11 +29,73 @@ impl PhysicsGuru {
     }
 
     pub fn resolve(mut self, dt: f32) -> PhysicsReply {
+        let step_start = Instant::now();
         if dt > ::std::f32::EPSILON { self.world.step(dt) }
-        PhysicsReply { _me: (), }
+        PhysicsReply {
+            step_start,
+            step_time: step_start.elapsed(),
+        }
     }
 }
 
 pub struct PhysicsReply {
-    _me: (),
+    /// When the physics step started, for the profiler.
+    pub step_start: Instant,
+    /// How long the physics step took.
+    pub step_time: Duration,
+}
+
+fn zero() -> Vector3<f32> { na::zero() }
+
+/// The material of a dynamic rigid body, as passed to `RigidBody::new_dynamic`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct BodyParams {
+    pub density: f32,
+    pub restitution: f32,
+    pub friction: f32,
+}
+
+/// Where a rigid body is and how it is moving.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BodyState {
+    pub position: Isometry3<f32>,
+    #[serde(default = "zero")]
+    pub lin_vel: Vector3<f32>,
+    #[serde(default = "zero")]
+    pub ang_vel: Vector3<f32>,
+    #[serde(default)]
+    pub sleeping: bool,
+}
+
+impl BodyState {
+    pub fn new(body: &RigidBody<f32>) -> BodyState {
+        BodyState {
+            position: *body.position(),
+            lin_vel: body.lin_vel(),
+            ang_vel: body.ang_vel(),
+            sleeping: !body.is_active(),
+        }
+    }
+
+    /// A body at rest.
+    pub fn at(position: Isometry3<f32>) -> BodyState {
+        BodyState {
+            position,
+            lin_vel: na::zero(),
+            ang_vel: na::zero(),
+            sleeping: false,
+        }
+    }
+
+    /// Move a body to this position, and set it moving the same way.
+    pub fn apply(&self, body: &mut RigidBody<f32>) {
+        body.set_transformation(self.position);
+        body.set_lin_vel(self.lin_vel);
+        body.set_ang_vel(self.ang_vel);
+        if self.sleeping {
+            body.deactivate();
+        } else if let Some(threshold) = body.deactivation_threshold() {
+            body.activate(threshold);
+        }
+    }
 }
@@ -0,0 +1,247 @@
+//! A list of every asset the shell needs, and checks that they are usable.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+
+use serde_json;
+use image::{self, ColorType, GenericImage};
+use image::hdr::HDRDecoder;
+
+use flight::load;
+
+use super::assets::resolve;
+use super::collision::open_collision;
+use super::environment::CUBE_SIDES;
+
+/// Where the manifest is read from.
+pub const MANIFEST_PATH: &str = "assets/manifest.json";
+
+/// Textures every object directory must contain.
+const OBJECT_TEXTURES: [&str; 3] = ["albedo.png", "normal.png", "knobs.png"];
+
+/// Every asset the shell needs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AssetManifest {
+    /// Object directories, see `open_object_directory`.
+    #[serde(default)]
+    pub objects: Vec<PathBuf>,
+    /// Wavefront files loaded without textures.
+    #[serde(default)]
+    pub models: Vec<PathBuf>,
+    #[serde(default)]
+    pub environments: Vec<EnvironmentDesc>,
+    #[serde(default)]
+    pub fonts: Vec<PathBuf>,
+}
+
+/// A directory of prefiltered cubemaps, named `radiance_{level}_{side}.hdr`
+/// and `irradiance_{side}.hdr`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnvironmentDesc {
+    pub name: String,
+    pub path: PathBuf,
+    pub radiance_levels: u8,
+    /// If the sun is drawn and lights objects, in addition to the cubemaps.
+    #[serde(default)]
+    pub sun_included: bool,
+    #[serde(default = "default_sun_color")]
+    pub sun_color: [f32; 4],
+}
+
+fn default_sun_color() -> [f32; 4] { [1., 1., 1., 0.] }
+
+/// Something wrong with an asset.
+#[derive(Debug, Clone)]
+pub struct Problem {
+    pub path: PathBuf,
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.message)
+    }
+}
+
+fn problem<P: Into<PathBuf>, M: Into<String>>(path: P, message: M) -> Problem {
+    Problem { path: path.into(), message: message.into() }
+}
+
+impl AssetManifest {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<AssetManifest> {
+        let file = BufReader::new(File::open(path)?);
+        serde_json::from_reader(file)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Find the environment with the given name.
+    pub fn environment(&self, name: &str) -> Option<&EnvironmentDesc> {
+        self.environments.iter().find(|e| e.name == name)
+    }
+
+    /// Check every asset in the manifest, loading each one.
+    pub fn check(&self) -> Vec<Problem> {
+        self.check_with(true)
+    }
+
+    /// Check that every file in the manifest exists, without loading them.
+    pub fn check_exists(&self) -> Vec<Problem> {
+        self.check_with(false)
+    }
+
+    /// Check every asset, loading them if `decode` is set.
+    fn check_with(&self, decode: bool) -> Vec<Problem> {
+        let mut problems = Vec::new();
+        for dir in &self.objects {
+            check_object(&resolve(dir), decode, &mut problems);
+        }
+        for model in &self.models {
+            check_model(&resolve(model), decode, &mut problems);
+        }
+        for env in &self.environments {
+            check_environment(env, decode, &mut problems);
+        }
+        for font in &self.fonts {
+            let font = resolve(font);
+            if !font.is_file() {
+                problems.push(problem(font, "missing font"));
+            }
+        }
+        problems
+    }
+}
+
+fn check_model(path: &Path, decode: bool, problems: &mut Vec<Problem>) {
+    if !path.is_file() {
+        problems.push(problem(path, "missing model"));
+        return;
+    }
+    if !decode {
+        return;
+    }
+    match load::open_wavefront(path) {
+        Ok(ref m) if m.verts.is_empty() => problems.push(problem(path, "model has no vertices")),
+        Ok(_) => (),
+        Err(e) => problems.push(problem(path, format!("could not parse model: {:?}", e))),
+    }
+}
+
+fn check_object(dir: &Path, decode: bool, problems: &mut Vec<Problem>) {
+    if !dir.is_dir() {
+        problems.push(problem(dir, "missing object directory"));
+        return;
+    }
+    check_model(&dir.join("model.obj"), decode, problems);
+
+    // Textures are sampled together, so they must match
+    let mut size = None;
+    for name in &OBJECT_TEXTURES {
+        let path = dir.join(name);
+        if !path.is_file() {
+            problems.push(problem(path, "missing texture"));
+            continue;
+        }
+        if !decode {
+            continue;
+        }
+        let img = match image::open(&path) {
+            Ok(i) => i,
+            Err(e) => {
+                problems.push(problem(path, format!("could not read texture: {}", e)));
+                continue;
+            },
+        };
+        match img.color() {
+            ColorType::RGB(8) | ColorType::RGBA(8) => (),
+            c => problems.push(problem(path.clone(), format!("texture is {:?}, expected 8-bit RGB or RGBA", c))),
+        }
+        let dims = img.dimensions();
+        if let Some((first, ref first_path)) = size {
+            if first != dims {
+                problems.push(problem(path.clone(), format!(
+                    "texture is {}x{}, but {} is {}x{}",
+                    dims.0, dims.1,
+                    first_path.display(),
+                    first.0, first.1,
+                )));
+            }
+        }
+        if size.is_none() {
+            size = Some((dims, path));
+        }
+    }
+
+    if !decode {
+        return;
+    }
+    if let Some(desc) = open_collision(dir) {
+        if let Err(e) = desc.compound(dir, 1.) {
+            problems.push(problem(dir.join("collision.json"), format!("could not build shape: {:?}", e)));
+        }
+    }
+}
+
+/// Read the size of an HDR file.
+fn hdr_size(path: &Path) -> Result<(u32, u32), String> {
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    let decoder = HDRDecoder::new(BufReader::new(file)).map_err(|e| e.to_string())?;
+    let meta = decoder.metadata();
+    Ok((meta.width, meta.height))
+}
+
+/// Check that every face of a cubemap exists, and if `decode` is set that
+/// they are square and all have the given size if there is one.
+fn check_faces<F>(name: F, size: Option<u32>, decode: bool, problems: &mut Vec<Problem>) -> Option<u32>
+    where F: Fn(&str) -> PathBuf
+{
+    let mut size = size;
+    for side in &CUBE_SIDES {
+        let path = name(side);
+        if !path.is_file() {
+            problems.push(problem(path, "missing cubemap face"));
+            continue;
+        }
+        if !decode {
+            continue;
+        }
+        match hdr_size(&path) {
+            Ok((w, h)) if w != h =>
+                problems.push(problem(path, format!("cubemap face is {}x{}, expected a square", w, h))),
+            Ok((w, _)) => {
+                let expected = size.unwrap_or(w);
+                if expected != w {
+                    problems.push(problem(path, format!(
+                        "cubemap face is {}x{}, expected {}x{}",
+                        w, w, expected, expected,
+                    )));
+                }
+                size = Some(expected);
+            },
+            Err(e) => problems.push(problem(path, format!("could not read HDR: {}", e))),
+        }
+    }
+    size
+}
+
+fn check_environment(env: &EnvironmentDesc, decode: bool, problems: &mut Vec<Problem>) {
+    let dir = resolve(&env.path);
+    if !dir.is_dir() {
+        problems.push(problem(dir, "missing environment directory"));
+        return;
+    }
+    // Each radiance level is half the size of the last
+    let mut size = None;
+    for level in 0..env.radiance_levels {
+        let expected = size.map(|s: u32| (s / 2).max(1));
+        let found = check_faces(
+            |side| dir.join(format!("radiance_{}_{}.hdr", level, side)),
+            expected,
+            decode,
+            problems,
+        );
+        size = found.or(expected);
+    }
+    check_faces(|side| dir.join(format!("irradiance_{}.hdr", side)), None, decode, problems);
+}
@@ -1,7 +1,13 @@
 pub mod gurus;
+pub mod assets;
+pub mod environment;
+pub mod collision;
+pub mod manifest;
+pub mod settings;
 
 use std::collections::HashMap;
-use std::path::Path;
+use std::io;
+use std::path::{Path, PathBuf};
 
 use gfx;
 use nalgebra::{Vector3, Point2};
@@ -11,7 +17,12 @@ use flight::draw::{DrawParams, Painter, UberMaterial, UberStyle, SolidStyle, Uni
 use flight::mesh::*;
 
 use self::gurus::*;
+use self::assets::{AssetWatcher, resolve};
+use self::collision::{CollisionShape, open_collision};
+use self::settings::SettingsStore;
+use app::AppInfo;
 use geo::*;
+use text::{GlyphAtlas, TextPainter};
 
 pub struct Meshes<R: gfx::Resources> {
     // UI Elements
@@ -20,31 +31,89 @@ pub struct Meshes<R: gfx::Resources> {
     pub floor: UberMesh<R>,
     pub slider_control: UberMesh<R>,
     pub slider_frame: UberMesh<R>,
+    pub button: UberMesh<R>,
+    pub button_active: UberMesh<R>,
 
     // Rays
     pub red_ray: Mesh<R, VertC, ()>,
     pub blue_ray: Mesh<R, VertC, ()>,
+
+    // Meshes opened with `load_object`, by object directory
+    pub objects: HashMap<PathBuf, UberMesh<R>>,
+    // Collision shapes of the objects that have them
+    pub shapes: HashMap<PathBuf, CollisionShape>,
+    // Scale each object's collision shape was built at
+    pub shape_scales: HashMap<PathBuf, f32>,
+    // Object directories of app icons, by app id
+    pub icons: HashMap<String, PathBuf>,
 }
 
 pub struct Painters<R: gfx::Resources> {
     pub solid: Painter<R, SolidStyle<R>>,
     pub uber: Painter<R, UberStyle<R>>,
     pub unishade: Painter<R, UnishadeStyle<R>>,
+    pub text: TextPainter<R>,
 }
 
 pub struct Gurus {
     pub interact: interact::InteractGuru,
     pub physics: physics::PhysicsGuru,
+    pub menu: menu::MenuGuru,
+    pub history: history::HistoryGuru,
 }
 
 pub struct GuruReply {
     pub interact: interact::InteractionReply,
     pub physics: physics::PhysicsReply,
+    pub history: history::HistoryReply,
 }
 
 pub struct Meta {
-    pub physics_speed: f32,
-    pub active_apps: HashMap<String, bool>,
+    /// Settings shared by the shell and every app.
+    pub settings: SettingsStore,
+    /// Every registered app, in update order.
+    pub apps: Vec<AppInfo>,
+    /// Names of every environment in the asset manifest.
+    pub environments: Vec<String>,
+    /// Haptic pulses requested during the frame.
+    pub pulses: Vec<interact::HapticPulse>,
+}
+
+impl Meta {
+    /// How fast physics runs, zero when paused.
+    pub fn physics_speed(&self) -> f32 {
+        if self.settings.get(&settings::PHYSICS_PAUSED) {
+            0.
+        } else {
+            self.settings.get(&settings::PHYSICS_SPEED)
+        }
+    }
+
+    /// Name of the environment map to show: the backdrop of the first running
+    /// app that has one, otherwise the shell's.
+    pub fn environment(&self) -> String {
+        self.apps.iter()
+            .filter(|a| self.is_active(&a.id))
+            .map(|a| self.settings.get(&settings::app_environment(&a.id)))
+            .find(|e| !e.is_empty())
+            .unwrap_or_else(|| self.settings.get(&settings::ENVIRONMENT))
+    }
+
+    /// If `environment` may have changed during the previous frame.
+    pub fn environment_changed(&self) -> bool {
+        self.settings.changed(&settings::ENVIRONMENT) || self.settings.changes().any(|k| {
+            k.starts_with("apps/") && (k.ends_with("/active") || k.ends_with("/environment"))
+        })
+    }
+
+    /// If the app with the given id is running.
+    pub fn is_active(&self, id: &str) -> bool {
+        self.settings.get(&settings::app_active(id))
+    }
+
+    pub fn set_active(&mut self, id: &str, active: bool) {
+        self.settings.set(&settings::app_active(id), active);
+    }
 }
 
 pub struct Common<R: gfx::Resources, C: gfx::CommandBuffer<R>> {
@@ -98,62 +167,186 @@ pub fn open_simple_object<P, R, F>(
     -> Result<UberMesh<R>, Error>
     where P: AsRef<Path>, R: gfx::Resources, F: gfx::Factory<R>
 {
-    Ok(load::open_wavefront(path)?.compute_tan().with_material(
+    Ok(load::open_wavefront(resolve(path))?.compute_tan().with_material(
         simple_material(f, albedo, metalness, roughness, flatness)?
     ).upload(f))
 }
 
-pub fn open_object_directory<P, R, F>(f: &mut F, path: P)
-    -> Result<UberMesh<R>, Error>
+/// Open the mesh in an object directory, and its collision shape, scaled by
+/// `scale`, if it has a `collision.json`.
+pub fn open_object_directory<P, R, F>(f: &mut F, path: P, scale: f32)
+    -> Result<(UberMesh<R>, Option<CollisionShape>), Error>
     where P: AsRef<Path>, R: gfx::Resources, F: gfx::Factory<R>
 {
-    let path = path.as_ref();
-    load::open_uber_mesh(
+    let path = resolve(path);
+    let mesh = load::open_uber_mesh(
         f, 
         path.join("model.obj"),
         path.join("albedo.png"),
         path.join("normal.png"),
-        path.join("knobs.png"))
+        path.join("knobs.png"))?;
+    let shape = match open_collision(&path) {
+        Some(desc) => Some(desc.compound(&path, scale)?),
+        None => None,
+    };
+    Ok((mesh, shape))
+}
+
+const CONTROLLER_OBJ: &str = "assets/controller.obj";
+const SLIDER_CONTROL_OBJ: &str = "assets/slider/control.obj";
+const SLIDER_FRAME_OBJ: &str = "assets/slider/frame.obj";
+
+/// Files loaded by `Meshes::new` that are reloaded when they change, and
+/// their metalness.
+const SIMPLE_OBJECTS: &[(&str, f32)] = &[
+    (CONTROLLER_OBJ, 1.),
+    (SLIDER_CONTROL_OBJ, 0.),
+    (SLIDER_FRAME_OBJ, 0.),
+];
+
+/// Open one of the `SIMPLE_OBJECTS`.
+fn open_builtin_object<R, F>(f: &mut F, path: &str) -> Result<UberMesh<R>, Error>
+    where R: gfx::Resources, F: gfx::Factory<R>
+{
+    let metalness = SIMPLE_OBJECTS.iter().find(|o| o.0 == path).map_or(0., |o| o.1);
+    open_simple_object(f, path, [0.6, 0.6, 0.6], metalness, 0.2, 0.)
 }
 
 impl<R: gfx::Resources> Meshes<R> {
     pub fn new<F: gfx::Factory<R>>(factory: &mut F) -> Result<Meshes<R>, Error> {
         Ok(Meshes {
-            controller: open_simple_object(
-                factory,
-                "assets/controller.obj",
-                [0.6, 0.6, 0.6],
-                1.,
-                0.2,
-                0.)?,
+            controller: open_builtin_object(factory, CONTROLLER_OBJ)?,
             wire_box: grid_lines(1, Vector3::new(1., 1., 1.)).upload(factory),
             floor: plane(2.5)
                 .with_tex(Point2::new(0., 0.))
                 .compute_tan()
                 .with_material(simple_material(factory, [0.8, 0.8, 0.8], 0., 0.6, 0.)?)
                 .upload(factory),
-            slider_control: open_simple_object(
-                factory,
-                "assets/slider/control.obj",
-                [0.6, 0.6, 0.6],
-                0.,
-                0.2,
-                0.)?,
-            slider_frame: open_simple_object(
-                factory,
-                "assets/slider/frame.obj",
-                [0.6, 0.6, 0.6],
-                0.,
-                0.2,
-                0.)?,
+            slider_control: open_builtin_object(factory, SLIDER_CONTROL_OBJ)?,
+            slider_frame: open_builtin_object(factory, SLIDER_FRAME_OBJ)?,
+            button: beveled_cube(1., 0.15)
+                .with_tex(Point2::new(0., 0.))
+                .compute_tan()
+                .with_material(simple_material(factory, [0.6, 0.6, 0.6], 0., 0.4, 0.)?)
+                .upload(factory),
+            button_active: beveled_cube(1., 0.15)
+                .with_tex(Point2::new(0., 0.))
+                .compute_tan()
+                .with_material(simple_material(factory, [0.2, 0.5, 0.9], 0., 0.4, 0.)?)
+                .upload(factory),
             red_ray: make_ray([1., 0., 0.]).upload(factory),
             blue_ray: make_ray([0., 0., 1.]).upload(factory),
+            objects: HashMap::new(),
+            shapes: HashMap::new(),
+            shape_scales: HashMap::new(),
+            icons: HashMap::new(),
         })
     }
+
+    /// Open an object directory, unless it is already open.
+    pub fn load_object<F, P>(&mut self, factory: &mut F, path: P) -> Result<(), Error>
+        where F: gfx::Factory<R>, P: AsRef<Path>
+    {
+        self.load_scaled_object(factory, path, 1.)
+    }
+
+    /// Open an object directory that is drawn scaled by `scale`, unless it is
+    /// already open. Its collision shape is scaled to match.
+    pub fn load_scaled_object<F, P>(&mut self, factory: &mut F, path: P, scale: f32)
+        -> Result<(), Error>
+        where F: gfx::Factory<R>, P: AsRef<Path>
+    {
+        let path = path.as_ref();
+        if !self.objects.contains_key(path) {
+            let (mesh, shape) = open_object_directory(factory, path, scale)?;
+            self.objects.insert(path.to_owned(), mesh);
+            self.shape_scales.insert(path.to_owned(), scale);
+            if let Some(shape) = shape {
+                self.shapes.insert(path.to_owned(), shape);
+            }
+        }
+        Ok(())
+    }
+
+    /// Get a mesh opened with `load_object`. Panics if it was never loaded.
+    pub fn object<P: AsRef<Path>>(&self, path: P) -> &UberMesh<R> {
+        &self.objects[path.as_ref()]
+    }
+
+    /// Get the collision shape of an object opened with `load_object`.
+    pub fn shape<P: AsRef<Path>>(&self, path: P) -> Option<&CollisionShape> {
+        self.shapes.get(path.as_ref())
+    }
+
+    /// Get the collision shape of an object opened with `load_object`, failing
+    /// if it has no valid `collision.json`.
+    pub fn require_shape<P: AsRef<Path>>(&self, path: P) -> Result<&CollisionShape, Error> {
+        let path = path.as_ref();
+        match self.shape(path) {
+            Some(s) => Ok(s),
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} has no valid collision.json", path.display()),
+            ).into()),
+        }
+    }
+
+    /// Get the icon of an app, if it has one.
+    pub fn icon(&self, id: &str) -> Option<&UberMesh<R>> {
+        self.icons.get(id).and_then(|p| self.objects.get(p))
+    }
+
+    /// Watch every file these meshes were loaded from.
+    pub fn watch(&self, watcher: &mut AssetWatcher) {
+        for path in self.objects.keys() {
+            watcher.watch(path.clone());
+        }
+        for &(path, _) in SIMPLE_OBJECTS {
+            watcher.watch(path);
+        }
+    }
+
+    /// Reload the meshes loaded from any of the changed paths. Meshes that
+    /// fail to load are kept as they were.
+    pub fn reload<F: gfx::Factory<R>>(&mut self, factory: &mut F, changed: &[PathBuf]) {
+        for path in changed {
+            if let Some(mesh) = self.objects.get_mut(path) {
+                let scale = self.shape_scales.get(path).cloned().unwrap_or(1.);
+                match open_object_directory(factory, path, scale) {
+                    Ok((m, shape)) => {
+                        *mesh = m;
+                        match shape {
+                            Some(s) => { self.shapes.insert(path.clone(), s); },
+                            None => { self.shapes.remove(path); },
+                        }
+                        info!("Reloaded {}", path.display());
+                    },
+                    Err(e) => warn!("Could not reload {}: {:?}", path.display(), e),
+                }
+            }
+        }
+
+        for &(simple, _) in SIMPLE_OBJECTS {
+            if !changed.iter().any(|p| p.as_path() == Path::new(simple)) {
+                continue;
+            }
+            match open_builtin_object(factory, simple) {
+                Ok(m) => {
+                    match simple {
+                        CONTROLLER_OBJ => self.controller = m,
+                        SLIDER_CONTROL_OBJ => self.slider_control = m,
+                        _ => self.slider_frame = m,
+                    }
+                    info!("Reloaded {}", simple);
+                },
+                Err(e) => warn!("Could not reload {}: {:?}", simple, e),
+            }
+        }
+    }
 }
 
 impl<R: gfx::Resources> Painters<R> {
-    pub fn new<F: gfx::Factory<R>>(factory: &mut F) -> Result<Painters<R>, Error> {
+    pub fn new<F: gfx::Factory<R>>(factory: &mut F, font: &GlyphAtlas) -> Result<Painters<R>, Error> {
         let mut solid = Painter::new(factory)?;
         solid.setup(factory, Primitive::LineList)?;
         solid.setup(factory, Primitive::TriangleList)?;
@@ -168,6 +361,7 @@ impl<R: gfx::Resources> Painters<R> {
             solid: solid,
             uber: uber,
             unishade: unishade,
+            text: TextPainter::new(factory, font, [1., 1., 1.])?,
         })
     }
 }
@@ -179,6 +373,7 @@ impl<R: gfx::Resources, C: gfx::CommandBuffer<R>> Common<R, C> {
             reply: GuruReply {
                 interact: self.gurus.interact.resolve(),
                 physics: self.gurus.physics.resolve(dt),
+                history: self.gurus.history.resolve(),
             },
             painters: self.painters,
             meshes: self.meshes,
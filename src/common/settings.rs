@@ -0,0 +1,463 @@
+//! Settings shared by the shell and every app.
+//!
+//! Each setting is named by a namespaced key like `physics/speed`, and must be
+//! defined with a default before it is used. Any app can read or write any
+//! setting through `Meta::settings`. Changes are visible immediately, and are
+//! reported by `SettingsStore::changed` for the whole next frame, so every app
+//! gets a chance to see them no matter the update order.
+//!
+//! Settings are saved to a single file named `SETTINGS_NAME` in the states
+//! directory, separate from the states of the apps.
+
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::marker::PhantomData;
+use std::mem;
+use std::path::Path;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::{self, Value};
+
+use app::autosave::SavedState;
+use app::state::{self, StateFormat, VersionedState};
+
+/// Name of the settings file in the states directory, without an extension.
+/// See `StateFormat::path`. The Settings app saves its own state as
+/// `settings`.
+pub const SETTINGS_NAME: &str = "shared-settings";
+
+pub const MIN_PHYSICS_SPEED: f32 = 0.1;
+pub const MAX_PHYSICS_SPEED: f32 = 4.;
+
+/// How fast physics runs, when it isn't paused.
+pub const PHYSICS_SPEED: Key<f32> = Key { name: Cow::Borrowed("physics/speed"), _type: PhantomData };
+pub const PHYSICS_PAUSED: Key<bool> = Key { name: Cow::Borrowed("physics/paused"), _type: PhantomData };
+/// Name of the environment map to show.
+pub const ENVIRONMENT: Key<String> = Key { name: Cow::Borrowed("shell/environment"), _type: PhantomData };
+
+/// If the app with the given id is running.
+pub fn app_active(id: &str) -> Key<bool> {
+    Key::new(format!("apps/{}/active", id))
+}
+
+/// Name of the environment map to show while the app is running, or empty to
+/// show `ENVIRONMENT`.
+pub fn app_environment(id: &str) -> Key<String> {
+    Key::new(format!("apps/{}/environment", id))
+}
+
+/// The name of a setting holding values of type `T`.
+pub struct Key<T> {
+    pub name: Cow<'static, str>,
+    _type: PhantomData<fn() -> T>,
+}
+
+impl<T> Key<T> {
+    pub fn new<N: Into<Cow<'static, str>>>(name: N) -> Key<T> {
+        Key { name: name.into(), _type: PhantomData }
+    }
+}
+
+impl<T> Clone for Key<T> {
+    fn clone(&self) -> Key<T> {
+        Key::new(self.name.clone())
+    }
+}
+
+/// The values a setting accepts, besides being the right type.
+#[derive(Debug, Clone)]
+pub enum Validation {
+    Any,
+    /// A number, clamped between the minimum and maximum.
+    Range(f64, f64),
+    /// One of a list of strings.
+    OneOf(Vec<String>),
+}
+
+struct Definition {
+    default: Value,
+    validation: Validation,
+    /// Checks the type of a value, since values are stored untyped.
+    check: fn(&Value) -> bool,
+}
+
+fn check<T: DeserializeOwned>(value: &Value) -> bool {
+    serde_json::from_value::<T>(value.clone()).is_ok()
+}
+
+impl Definition {
+    fn validate(&self, value: Value) -> Result<Value, String> {
+        if !(self.check)(&value) {
+            return Err(format!("{} is the wrong type", value));
+        }
+        match self.validation {
+            Validation::Any => Ok(value),
+            Validation::Range(min, max) => {
+                let clamped = match value.as_f64() {
+                    Some(v) if v < min => Value::from(min),
+                    Some(v) if v > max => Value::from(max),
+                    Some(_) => return Ok(value),
+                    None => return Err(format!("{} is not a number", value)),
+                };
+                // A bound may not fit the type, like a fraction for an integer
+                if (self.check)(&clamped) {
+                    Ok(clamped)
+                } else {
+                    Err(format!("{} is out of range and {} is the wrong type", value, clamped))
+                }
+            },
+            Validation::OneOf(ref options) => {
+                if value.as_str().map_or(false, |v| options.iter().any(|o| o == v)) {
+                    Ok(value)
+                } else {
+                    Err(format!("{} is not one of {:?}", value, options))
+                }
+            },
+        }
+    }
+}
+
+/// The settings file, by key.
+#[derive(Serialize, Deserialize)]
+struct SavedSettings(HashMap<String, Value>);
+
+impl VersionedState for SavedSettings {
+    const VERSION: u32 = 1;
+}
+
+pub struct SettingsStore {
+    definitions: HashMap<String, Definition>,
+    /// Values that differ from the defaults, or that were loaded before they
+    /// were defined.
+    values: HashMap<String, Value>,
+    /// Keys changed since the last call to `end_frame`.
+    pending: HashSet<String>,
+    /// Keys changed during the previous frame.
+    changed: HashSet<String>,
+}
+
+impl SettingsStore {
+    pub fn new() -> SettingsStore {
+        SettingsStore {
+            definitions: HashMap::new(),
+            values: HashMap::new(),
+            pending: HashSet::new(),
+            changed: HashSet::new(),
+        }
+    }
+
+    /// Define a setting, or change the default and validation of an existing
+    /// one. A value that is no longer valid is reset to the default.
+    ///
+    /// Panics if the default itself is not valid.
+    pub fn define<T>(&mut self, key: &Key<T>, default: T, validation: Validation)
+        where T: Serialize + DeserializeOwned
+    {
+        let mut definition = Definition {
+            default: Value::Null,
+            validation,
+            check: check::<T>,
+        };
+        let default = serde_json::to_value(default).expect("Setting default is not serializable");
+        definition.default = match definition.validate(default) {
+            Ok(d) => d,
+            Err(e) => panic!("Default for setting {} is invalid: {}", key.name, e),
+        };
+        if let Some(value) = self.values.remove(&*key.name) {
+            match definition.validate(value) {
+                Ok(value) => { self.values.insert(key.name.to_string(), value); },
+                Err(e) => {
+                    warn!("Resetting setting {}: {}", key.name, e);
+                    self.pending.insert(key.name.to_string());
+                },
+            }
+        }
+        self.definitions.insert(key.name.to_string(), definition);
+    }
+
+    fn definition(&self, name: &str) -> &Definition {
+        match self.definitions.get(name) {
+            Some(d) => d,
+            None => panic!("Setting {} has not been defined", name),
+        }
+    }
+
+    pub fn get<T: DeserializeOwned>(&self, key: &Key<T>) -> T {
+        let value = match self.values.get(&*key.name) {
+            Some(v) => v.clone(),
+            None => self.definition(&key.name).default.clone(),
+        };
+        serde_json::from_value(value).expect("Setting has the wrong type")
+    }
+
+    /// Change a setting. Numbers are clamped into range, and other invalid
+    /// values are ignored with a warning. Returns true if the value changed.
+    pub fn set<T: Serialize>(&mut self, key: &Key<T>, value: T) -> bool {
+        let value = serde_json::to_value(value).expect("Setting is not serializable");
+        self.set_value(&key.name, value)
+    }
+
+    /// Change a setting back to its default.
+    pub fn reset<T: Serialize>(&mut self, key: &Key<T>) -> bool {
+        let default = self.definition(&key.name).default.clone();
+        self.set_value(&key.name, default)
+    }
+
+    fn set_value(&mut self, name: &str, value: Value) -> bool {
+        let value = match self.definitions.get(name) {
+            Some(d) => match d.validate(value) {
+                Ok(v) => v,
+                Err(e) => {
+                    warn!("Invalid value for setting {}: {}", name, e);
+                    return false;
+                },
+            },
+            // Kept until the setting is defined
+            None => value,
+        };
+        let current = self.values.get(name)
+            .or_else(|| self.definitions.get(name).map(|d| &d.default))
+            .cloned();
+        if current.as_ref() == Some(&value) {
+            return false;
+        }
+        if self.definitions.get(name).map_or(false, |d| d.default == value) {
+            self.values.remove(name);
+        } else {
+            self.values.insert(name.to_owned(), value);
+        }
+        self.pending.insert(name.to_owned());
+        true
+    }
+
+    /// If the setting changed during the previous frame.
+    pub fn changed<T>(&self, key: &Key<T>) -> bool {
+        self.changed.contains(&*key.name)
+    }
+
+    /// Every key changed during the previous frame.
+    pub fn changes<'a>(&'a self) -> impl Iterator<Item = &'a str> + 'a {
+        self.changed.iter().map(|k| k.as_str())
+    }
+
+    /// Start reporting the changes made since the last call. Called by the
+    /// shell after every frame.
+    pub fn end_frame(&mut self) {
+        self.changed = mem::replace(&mut self.pending, HashSet::new());
+    }
+
+    /// Every setting that differs from its default, as saved in the settings
+    /// file.
+    pub fn save(&self) -> Result<Value, serde_json::Error> {
+        state::save(&SavedSettings(self.values.clone()))
+    }
+
+    /// Replace every setting with a saved one. Settings missing from `saved`
+    /// go back to their defaults.
+    pub fn load(&mut self, saved: Value) -> Result<(), serde_json::Error> {
+        let SavedSettings(values) = state::load(saved)?;
+        let names: Vec<String> = self.values.keys()
+            .chain(values.keys())
+            .cloned()
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        for name in names {
+            let value = match values.get(&name) {
+                Some(v) => v.clone(),
+                None => match self.definitions.get(&name) {
+                    Some(d) => d.default.clone(),
+                    None => {
+                        self.values.remove(&name);
+                        continue;
+                    },
+                },
+            };
+            self.set_value(&name, value);
+        }
+        Ok(())
+    }
+
+    /// Load the settings file, see `state::load_saved`.
+    pub fn open(&mut self, states_dir: &Path, format: StateFormat, backups: usize) -> bool {
+        state::load_saved("settings", &states_dir.join(SETTINGS_NAME), format, backups, |value| {
+            self.load(value).map_err(|e| e.to_string())
+        })
+    }
+
+    /// Encode the settings file, for the `Autosaver`.
+    pub fn saved(&self, states_dir: &Path, format: StateFormat) -> Option<SavedState> {
+        let result = self.save()
+            .map_err(|e| e.to_string())
+            .and_then(|value| format.encode(&value));
+        match result {
+            Ok(data) => Some((format.path(states_dir.join(SETTINGS_NAME)), data)),
+            Err(e) => {
+                event!(Error, "settings_save_failed", error = e);
+                None
+            },
+        }
+    }
+}
+
+/// Define the settings used by the shell itself.
+pub fn define_shell(settings: &mut SettingsStore, environments: &[String]) {
+    settings.define(
+        &PHYSICS_SPEED,
+        1.,
+        Validation::Range(MIN_PHYSICS_SPEED as f64, MAX_PHYSICS_SPEED as f64),
+    );
+    settings.define(&PHYSICS_PAUSED, false, Validation::Any);
+    // Without any environments, only the empty name is valid
+    let options = if environments.is_empty() {
+        vec![String::new()]
+    } else {
+        environments.to_vec()
+    };
+    settings.define(&ENVIRONMENT, options[0].clone(), Validation::OneOf(options));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPEED: Key<f32> = Key { name: Cow::Borrowed("test/speed"), _type: PhantomData };
+    const COLOR: Key<String> = Key { name: Cow::Borrowed("test/color"), _type: PhantomData };
+    const ENABLED: Key<bool> = Key { name: Cow::Borrowed("test/enabled"), _type: PhantomData };
+
+    fn store() -> SettingsStore {
+        let mut settings = SettingsStore::new();
+        settings.define(&SPEED, 1., Validation::Range(0.5, 2.));
+        settings.define(
+            &COLOR,
+            "red".to_owned(),
+            Validation::OneOf(vec!["red".to_owned(), "blue".to_owned()]),
+        );
+        settings.define(&ENABLED, false, Validation::Any);
+        settings
+    }
+
+    #[test]
+    fn defaults() {
+        let settings = store();
+        assert_eq!(settings.get(&SPEED), 1.);
+        assert_eq!(settings.get(&COLOR), "red");
+        assert_eq!(settings.get(&ENABLED), false);
+    }
+
+    #[test]
+    fn range_clamps() {
+        let mut settings = store();
+        assert!(settings.set(&SPEED, 10.));
+        assert_eq!(settings.get(&SPEED), 2.);
+        assert!(settings.set(&SPEED, 0.));
+        assert_eq!(settings.get(&SPEED), 0.5);
+        assert!(!settings.set(&SPEED, 0.1));
+    }
+
+    #[test]
+    fn one_of_rejects_others() {
+        let mut settings = store();
+        assert!(settings.set(&COLOR, "blue".to_owned()));
+        assert!(!settings.set(&COLOR, "green".to_owned()));
+        assert_eq!(settings.get(&COLOR), "blue");
+    }
+
+    #[test]
+    fn wrong_type_rejected() {
+        let mut settings = store();
+        let wrong: Key<String> = Key::new("test/enabled");
+        assert!(!settings.set(&wrong, "yes".to_owned()));
+        assert_eq!(settings.get(&ENABLED), false);
+    }
+
+    #[test]
+    fn reset_to_default() {
+        let mut settings = store();
+        settings.set(&ENABLED, true);
+        assert!(settings.reset(&ENABLED));
+        assert_eq!(settings.get(&ENABLED), false);
+        assert!(!settings.reset(&ENABLED));
+    }
+
+    #[test]
+    fn changes_reported_next_frame() {
+        let mut settings = store();
+        settings.set(&ENABLED, true);
+        assert!(!settings.changed(&ENABLED));
+
+        settings.end_frame();
+        assert!(settings.changed(&ENABLED));
+        assert!(!settings.changed(&SPEED));
+        assert_eq!(settings.changes().collect::<Vec<_>>(), vec!["test/enabled"]);
+
+        settings.end_frame();
+        assert!(!settings.changed(&ENABLED));
+    }
+
+    #[test]
+    fn unchanged_value_not_reported() {
+        let mut settings = store();
+        assert!(!settings.set(&SPEED, 1.));
+        settings.end_frame();
+        assert!(!settings.changed(&SPEED));
+    }
+
+    #[test]
+    fn save_and_load() {
+        let mut settings = store();
+        settings.set(&SPEED, 1.5);
+        settings.set(&COLOR, "blue".to_owned());
+        let saved = settings.save().unwrap();
+
+        let mut loaded = store();
+        loaded.set(&ENABLED, true);
+        loaded.load(saved).unwrap();
+        assert_eq!(loaded.get(&SPEED), 1.5);
+        assert_eq!(loaded.get(&COLOR), "blue");
+        // Missing from the save, so back to the default
+        assert_eq!(loaded.get(&ENABLED), false);
+    }
+
+    #[test]
+    fn loaded_before_defined() {
+        let mut saved = store();
+        saved.set(&SPEED, 1.5);
+        saved.set(&COLOR, "blue".to_owned());
+        let saved = saved.save().unwrap();
+
+        let mut settings = SettingsStore::new();
+        settings.load(saved).unwrap();
+        settings.define(&SPEED, 1., Validation::Range(0.5, 2.));
+        settings.define(&COLOR, "red".to_owned(), Validation::OneOf(vec!["red".to_owned()]));
+        assert_eq!(settings.get(&SPEED), 1.5);
+        // No longer valid once defined
+        assert_eq!(settings.get(&COLOR), "red");
+    }
+
+    #[test]
+    fn clamped_value_keeps_type() {
+        let mut settings = store();
+        let count: Key<u32> = Key::new("test/count");
+        settings.define(&count, 1, Validation::Range(0.5, 10.));
+        assert!(settings.set(&count, 10));
+        // Clamping to 0.5 would no longer be a u32
+        assert!(!settings.set(&count, 0));
+        assert_eq!(settings.get(&count), 10);
+    }
+
+    #[test]
+    #[should_panic]
+    fn invalid_default_panics() {
+        let mut settings = store();
+        settings.define(&COLOR, "green".to_owned(), Validation::OneOf(vec!["red".to_owned()]));
+    }
+
+    #[test]
+    fn shell_without_environments() {
+        let mut settings = SettingsStore::new();
+        define_shell(&mut settings, &[]);
+        assert_eq!(settings.get(&ENVIRONMENT), "");
+    }
+}
@@ -0,0 +1,414 @@
+//! Startup configuration, read in layers.
+//!
+//! Each option starts at its default, then may be overridden by a config file,
+//! then by an environment variable, then by a command line flag. The config
+//! file is `config.toml` or `config.json`, or the file given with `--config`
+//! or `VR_CONFIG`. Environment variables are named `VR_` followed by the
+//! option in upper case, like `VR_NEAR_PLANE`.
+
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use clap::{Arg, ArgMatches};
+use serde_json::{self, Value, Map};
+use toml;
+
+use app::state::StateFormat;
+use logging::LogFilter;
+
+/// Config files read when none is given, in order of preference.
+pub const CONFIG_PATHS: [&str; 2] = ["config.toml", "config.json"];
+
+/// Prefix of the environment variables that set options.
+const ENV_PREFIX: &str = "VR_";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Use the mock VR API.
+    pub mock: bool,
+    /// Format app states are saved in.
+    pub state_format: StateFormat,
+    /// Distance to the near clipping plane, in meters.
+    pub near_plane: f64,
+    /// Distance to the far clipping plane, in meters.
+    pub far_plane: f64,
+    /// Longest time step simulated at once, in seconds.
+    pub max_step: f64,
+    pub gravity: [f32; 3],
+    /// Where app states are saved.
+    pub states_dir: PathBuf,
+    /// Where assets are read from, see `assets::set_root`.
+    pub asset_root: PathBuf,
+    /// Log levels, see `LogFilter`.
+    pub log_level: String,
+    /// A file to log to, in addition to the terminal.
+    pub log_file: Option<PathBuf>,
+    /// Size in bytes at which the log file is rotated.
+    pub log_file_size: u64,
+    /// Rotated log files kept.
+    pub log_files_kept: usize,
+    /// Ids of the apps to turn on at launch, replacing the saved settings.
+    pub apps: Option<Vec<String>>,
+    /// Show frame timings above the primary controller at launch.
+    pub profiler_hud: bool,
+    /// Where frame traces are written, see `Profiler::write_trace`. A trace is
+    /// also written at exit when this is set.
+    pub trace_file: Option<PathBuf>,
+    /// Frames kept for traces.
+    pub trace_frames: usize,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            mock: false,
+            state_format: StateFormat::Json,
+            near_plane: 0.1,
+            far_plane: 75.,
+            max_step: 0.02,
+            gravity: [0., -5., 0.],
+            states_dir: PathBuf::from("states"),
+            asset_root: PathBuf::from("assets"),
+            log_level: "info".to_owned(),
+            log_file: None,
+            log_file_size: 10_000_000,
+            log_files_kept: 5,
+            apps: None,
+            profiler_hud: false,
+            trace_file: None,
+            trace_frames: 600,
+        }
+    }
+}
+
+/// How an option is written on the command line and in the environment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    /// A flag without a value, which sets the option to true. The flag given
+    /// here, conventionally `no-` and the flag, sets it back to false.
+    Switch(&'static str),
+    /// A string, taken as is.
+    Text,
+    /// A number or an array, written as JSON.
+    Json,
+    /// A comma-separated list of strings.
+    List,
+}
+
+struct ConfigOption {
+    name: &'static str,
+    flag: &'static str,
+    short: Option<&'static str>,
+    value_name: &'static str,
+    kind: Kind,
+    help: &'static str,
+}
+
+const OPTIONS: &[ConfigOption] = &[
+    ConfigOption {
+        name: "mock", flag: "mock", short: Some("m"), value_name: "", kind: Kind::Switch("no-mock"),
+        help: "Use mock VR API",
+    },
+    ConfigOption {
+        name: "state_format", flag: "state-format", short: None, value_name: "FORMAT", kind: Kind::Text,
+        help: "Format app states are saved in: json, pretty-json, cbor or ron",
+    },
+    ConfigOption {
+        name: "near_plane", flag: "near-plane", short: None, value_name: "METERS", kind: Kind::Json,
+        help: "Distance to the near clipping plane",
+    },
+    ConfigOption {
+        name: "far_plane", flag: "far-plane", short: None, value_name: "METERS", kind: Kind::Json,
+        help: "Distance to the far clipping plane",
+    },
+    ConfigOption {
+        name: "max_step", flag: "max-step", short: None, value_name: "SECONDS", kind: Kind::Json,
+        help: "Longest time step simulated at once",
+    },
+    ConfigOption {
+        name: "gravity", flag: "gravity", short: None, value_name: "[X,Y,Z]", kind: Kind::Json,
+        help: "Gravity, in meters per second squared",
+    },
+    ConfigOption {
+        name: "states_dir", flag: "states-dir", short: None, value_name: "DIR", kind: Kind::Text,
+        help: "Where app states are saved",
+    },
+    ConfigOption {
+        name: "asset_root", flag: "asset-root", short: None, value_name: "DIR", kind: Kind::Text,
+        help: "Where assets are read from",
+    },
+    ConfigOption {
+        name: "log_level", flag: "log-level", short: None, value_name: "LEVEL", kind: Kind::Text,
+        help: "Log levels, like warn or info,interact=debug,app::snowflakes=trace",
+    },
+    ConfigOption {
+        name: "log_file", flag: "log-file", short: None, value_name: "FILE", kind: Kind::Text,
+        help: "Also log to a file",
+    },
+    ConfigOption {
+        name: "log_file_size", flag: "log-file-size", short: None, value_name: "BYTES", kind: Kind::Json,
+        help: "Size at which the log file is rotated",
+    },
+    ConfigOption {
+        name: "log_files_kept", flag: "log-files-kept", short: None, value_name: "COUNT", kind: Kind::Json,
+        help: "Rotated log files kept",
+    },
+    ConfigOption {
+        name: "apps", flag: "apps", short: None, value_name: "IDS", kind: Kind::List,
+        help: "Comma-separated ids of the apps to turn on at launch",
+    },
+    ConfigOption {
+        name: "profiler_hud", flag: "profiler-hud", short: None, value_name: "", kind: Kind::Switch("no-profiler-hud"),
+        help: "Show frame timings above the primary controller",
+    },
+    ConfigOption {
+        name: "trace_file", flag: "trace-file", short: None, value_name: "FILE", kind: Kind::Value,
+        help: "Write a Chrome trace of the last frames to a file at exit",
+    },
+    ConfigOption {
+        name: "trace_frames", flag: "trace-frames", short: None, value_name: "COUNT", kind: Kind::Value,
+        help: "Frames kept for traces",
+    },
+];
+
+/// Command line arguments for every option, and for `--config`.
+pub fn args() -> Vec<Arg<'static, 'static>> {
+    let mut args = vec![
+        Arg::with_name("config")
+            .long("config")
+            .takes_value(true)
+            .value_name("FILE")
+            .help("Read options from a TOML or JSON file"),
+    ];
+    for option in OPTIONS {
+        let mut arg = Arg::with_name(option.name)
+            .long(option.flag)
+            .help(option.help);
+        if let Some(short) = option.short {
+            arg = arg.short(short);
+        }
+        let negation = match option.kind {
+            Kind::Switch(negated) => {
+                // Whichever of the two comes last wins
+                arg = arg.overrides_with(negated);
+                Some(Arg::with_name(negated)
+                    .long(negated)
+                    .overrides_with(option.name)
+                    .help("Turn off a switch set by the config file or environment"))
+            },
+            _ => {
+                arg = arg.takes_value(true).value_name(option.value_name);
+                None
+            },
+        };
+        args.push(arg);
+        args.extend(negation);
+    }
+    args
+}
+
+fn parse_value(kind: Kind, text: &str) -> Value {
+    match kind {
+        Kind::Switch(_) => match text {
+            "" | "0" | "false" | "no" => Value::Bool(false),
+            _ => Value::Bool(true),
+        },
+        Kind::Text => Value::String(text.to_owned()),
+        // Left as a string when invalid, so the error names the option
+        Kind::Json => serde_json::from_str(text).unwrap_or_else(|_| Value::String(text.to_owned())),
+        Kind::List => Value::Array(text.split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(|s| Value::String(s.to_owned()))
+            .collect()),
+    }
+}
+
+fn read_file(path: &Path) -> Result<Map<String, Value>, String> {
+    let mut text = String::new();
+    File::open(path)
+        .and_then(|mut f| f.read_to_string(&mut text))
+        .map_err(|e| e.to_string())?;
+    let value = match path.extension().and_then(|e| e.to_str()) {
+        Some("json") => serde_json::from_str(&text).map_err(|e| e.to_string())?,
+        _ => {
+            let table: toml::Value = toml::from_str(&text).map_err(|e| e.to_string())?;
+            serde_json::to_value(table).map_err(|e| e.to_string())?
+        },
+    };
+    match value {
+        Value::Object(map) => Ok(map),
+        _ => Err("expected a table of options".to_owned()),
+    }
+}
+
+/// Check that a layer only has known options with valid values.
+fn check_layer(layer: &Map<String, Value>, source: &str) -> Result<(), String> {
+    serde_json::from_value::<Config>(Value::Object(layer.clone()))
+        .map(|_| ())
+        .map_err(|e| format!("{}: {}", source, e))
+}
+
+impl Config {
+    /// Read every layer of options. Fails with a message if any layer has
+    /// invalid options.
+    pub fn load(matches: &ArgMatches) -> Result<Config, String> {
+        Config::load_with(matches, |var| env::var(var).ok())
+    }
+
+    /// Read every layer of options, looking up environment variables with
+    /// `var`.
+    fn load_with<V>(matches: &ArgMatches, var: V) -> Result<Config, String>
+        where V: Fn(&str) -> Option<String>
+    {
+        let mut merged = match serde_json::to_value(Config::default()) {
+            Ok(Value::Object(map)) => map,
+            _ => unreachable!(),
+        };
+
+        // Config file
+        let explicit = matches.value_of("config").map(PathBuf::from)
+            .or_else(|| var(&format!("{}CONFIG", ENV_PREFIX)).map(PathBuf::from));
+        let path = match explicit {
+            Some(p) => Some(p),
+            None => CONFIG_PATHS.iter().map(PathBuf::from).find(|p| p.is_file()),
+        };
+        if let Some(path) = path {
+            let source = path.display().to_string();
+            let layer = read_file(&path).map_err(|e| format!("{}: {}", source, e))?;
+            check_layer(&layer, &source)?;
+            merged.extend(layer);
+        }
+
+        // Environment variables
+        let mut layer = Map::new();
+        for option in OPTIONS {
+            let name = format!("{}{}", ENV_PREFIX, option.name.to_uppercase());
+            if let Some(text) = var(&name) {
+                layer.insert(option.name.to_owned(), parse_value(option.kind, &text));
+            }
+        }
+        check_layer(&layer, "environment")?;
+        merged.extend(layer);
+
+        // Command line
+        let mut layer = Map::new();
+        for option in OPTIONS {
+            match option.kind {
+                Kind::Switch(negated) => {
+                    if matches.is_present(option.name) {
+                        layer.insert(option.name.to_owned(), Value::Bool(true));
+                    } else if matches.is_present(negated) {
+                        layer.insert(option.name.to_owned(), Value::Bool(false));
+                    }
+                },
+                _ => if let Some(text) = matches.value_of(option.name) {
+                    layer.insert(option.name.to_owned(), parse_value(option.kind, text));
+                },
+            }
+        }
+        check_layer(&layer, "command line")?;
+        merged.extend(layer);
+
+        let config: Config = serde_json::from_value(Value::Object(merged)).map_err(|e| e.to_string())?;
+        config.log_filter()?;
+        if config.near_plane <= 0. || config.far_plane <= config.near_plane {
+            return Err("the far plane must be further than the near plane, which must be positive".to_owned());
+        }
+        if config.max_step <= 0. {
+            return Err("the maximum step must be positive".to_owned());
+        }
+        Ok(config)
+    }
+
+    pub fn log_filter(&self) -> Result<LogFilter, String> {
+        LogFilter::from_str(&self.log_level)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::fs;
+    use std::io::Write;
+    use std::process;
+
+    use clap::App;
+
+    use super::*;
+
+    /// Load a config from a file with `contents`, environment variables and
+    /// command line arguments.
+    fn load(name: &str, contents: &str, vars: &[(&str, &str)], flags: &[&str]) -> Result<Config, String> {
+        let path = env::temp_dir().join(format!("config-test-{}-{}.toml", process::id(), name));
+        File::create(&path).and_then(|mut f| f.write_all(contents.as_bytes())).unwrap();
+        let mut argv = vec!["test".to_owned(), "--config".to_owned(), path.display().to_string()];
+        argv.extend(flags.iter().map(|f| f.to_string()));
+        let matches = App::new("test").args(&args()).get_matches_from(argv);
+        let vars: HashMap<String, String> = vars.iter()
+            .map(|&(k, v)| (k.to_owned(), v.to_owned()))
+            .collect();
+        let config = Config::load_with(&matches, |var| vars.get(var).cloned());
+        fs::remove_file(&path).unwrap();
+        config
+    }
+
+    #[test]
+    fn defaults() {
+        let config = load("defaults", "", &[], &[]).unwrap();
+        assert_eq!(config.near_plane, 0.1);
+        assert_eq!(config.states_dir, PathBuf::from("states"));
+        assert!(!config.mock);
+    }
+
+    #[test]
+    fn later_layers_win() {
+        let file = "near_plane = 0.2\nfar_plane = 50.0\nmax_step = 0.05\n";
+        let vars = [("VR_FAR_PLANE", "60"), ("VR_MAX_STEP", "0.03")];
+        let config = load("layers", file, &vars, &["--max-step", "0.01"]).unwrap();
+        assert_eq!(config.near_plane, 0.2);
+        assert_eq!(config.far_plane, 60.);
+        assert_eq!(config.max_step, 0.01);
+    }
+
+    #[test]
+    fn values_by_kind() {
+        let vars = [("VR_MOCK", "no"), ("VR_APPS", "halo, settings,")];
+        let flags = ["--states-dir", "123", "--gravity", "[0,-9.8,0]"];
+        let config = load("kinds", "mock = true\n", &vars, &flags).unwrap();
+        assert!(!config.mock);
+        assert_eq!(config.apps, Some(vec!["halo".to_owned(), "settings".to_owned()]));
+        assert_eq!(config.states_dir, PathBuf::from("123"));
+        assert_eq!(config.gravity, [0., -9.8, 0.]);
+    }
+
+    #[test]
+    fn switches_turned_off() {
+        let config = load("no-mock", "mock = true\n", &[], &["--no-mock"]).unwrap();
+        assert!(!config.mock);
+        let config = load("mock-last", "", &[], &["--no-mock", "--mock"]).unwrap();
+        assert!(config.mock);
+        let config = load("no-mock-last", "", &[], &["--mock", "--no-mock"]).unwrap();
+        assert!(!config.mock);
+    }
+
+    #[test]
+    fn invalid_layer_named() {
+        let err = load("unknown", "nope = 1\n", &[], &[]).unwrap_err();
+        assert!(err.contains("config-test"), "{}", err);
+        let err = load("env", "", &[("VR_NEAR_PLANE", "close")], &[]).unwrap_err();
+        assert!(err.starts_with("environment:"), "{}", err);
+        let err = load("flags", "", &[], &["--far-plane", "lots"]).unwrap_err();
+        assert!(err.starts_with("command line:"), "{}", err);
+    }
+
+    #[test]
+    fn planes_checked() {
+        assert!(load("planes", "", &[], &["--near-plane", "2", "--far-plane", "1"]).is_err());
+        assert!(load("step", "", &[], &["--max-step", "0"]).is_err());
+    }
+}
@@ -0,0 +1,299 @@
+//! Logging to the terminal and to a rotating file, filtered per module.
+//!
+//! Levels are given like `warn,interact=debug,app::snowflakes=trace`: a
+//! default level, then levels for modules of this crate or for other crates.
+//! The most specific module wins. A few subsystems have short names, see
+//! `ALIASES`.
+//!
+//! Events that are useful when going over a session after the fact are logged
+//! with `event!`, which writes them as `name key=value ...`.
+
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::{self, Log, LogLevelFilter, LogMetadata, LogRecord, SetLoggerError};
+use simplelog::{Config, TermLogger};
+
+use app::autosave::backup_path;
+
+/// Log a structured event, with a name and `key = value` fields. Values are
+/// written with `Debug`, so strings are quoted.
+///
+/// ```ignore
+/// event!(Debug, "grab", controller = index, from = "Free");
+/// ```
+macro_rules! event {
+    ($lvl:ident, $name:expr $(, $key:ident = $value:expr)* $(,)*) => {
+        log!(::log::LogLevel::$lvl, "{}", $crate::logging::Event {
+            name: $name,
+            fields: &[$((stringify!($key), &$value as &::std::fmt::Debug)),*],
+        })
+    };
+}
+
+/// A structured event, see `event!`.
+pub struct Event<'a> {
+    pub name: &'a str,
+    pub fields: &'a [(&'a str, &'a fmt::Debug)],
+}
+
+impl<'a> fmt::Display for Event<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        for &(key, value) in self.fields {
+            write!(f, " {}={:?}", key, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Short names for the modules of a subsystem.
+const ALIASES: &[(&str, &str)] = &[
+    ("interact", "common::gurus::interact"),
+    ("physics", "common::gurus::physics"),
+    ("apps", "app"),
+    ("state", "app::state"),
+    ("settings", "common::settings"),
+];
+
+/// Log levels by module.
+#[derive(Debug, Clone)]
+pub struct LogFilter {
+    pub default: LogLevelFilter,
+    /// Module paths, and the level for everything inside them.
+    pub modules: Vec<(String, LogLevelFilter)>,
+}
+
+fn crate_name() -> &'static str {
+    module_path!().split("::").next().unwrap()
+}
+
+/// If `target` is `module` or inside it.
+fn inside(target: &str, module: &str) -> bool {
+    target.starts_with(module)
+        && (target.len() == module.len() || target[module.len()..].starts_with("::"))
+}
+
+impl LogFilter {
+    /// The level for messages from a module.
+    pub fn level(&self, target: &str) -> LogLevelFilter {
+        let own = format!("{}::", crate_name());
+        let local = if target.starts_with(&own) { &target[own.len()..] } else { "" };
+        self.modules.iter()
+            .filter(|&&(ref module, _)| inside(target, module) || inside(local, module))
+            .max_by_key(|&&(ref module, _)| module.len())
+            .map(|&(_, level)| level)
+            .unwrap_or(self.default)
+    }
+
+    /// The most detailed level of any module.
+    pub fn max(&self) -> LogLevelFilter {
+        self.modules.iter().map(|&(_, level)| level).fold(self.default, ::std::cmp::max)
+    }
+}
+
+impl FromStr for LogFilter {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<LogFilter, String> {
+        fn level(text: &str) -> Result<LogLevelFilter, String> {
+            LogLevelFilter::from_str(text.trim()).map_err(|_| format!("unknown log level {}", text))
+        }
+
+        let mut filter = LogFilter {
+            default: LogLevelFilter::Info,
+            modules: Vec::new(),
+        };
+        for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            match part.find('=') {
+                Some(i) => {
+                    let name = part[..i].trim();
+                    let module = ALIASES.iter()
+                        .find(|&&(alias, _)| alias == name)
+                        .map_or(name, |&(_, module)| module);
+                    filter.modules.push((module.to_owned(), level(&part[i + 1..])?));
+                },
+                None => filter.default = level(part)?,
+            }
+        }
+        Ok(filter)
+    }
+}
+
+/// A log file that is moved aside once it grows too large. The old files are
+/// numbered like state backups, `.1` being the newest.
+pub struct RotatingFile {
+    path: PathBuf,
+    /// Size in bytes at which the file is rotated.
+    max_size: u64,
+    /// Old files kept.
+    keep: usize,
+    file: Mutex<(File, u64)>,
+}
+
+impl RotatingFile {
+    pub fn open<P: AsRef<Path>>(path: P, max_size: u64, keep: usize) -> io::Result<RotatingFile> {
+        let path = path.as_ref().to_owned();
+        if let Some(dir) = path.parent() {
+            if !dir.as_os_str().is_empty() {
+                fs::create_dir_all(dir)?;
+            }
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(RotatingFile {
+            path,
+            max_size,
+            keep,
+            file: Mutex::new((file, size)),
+        })
+    }
+
+    fn rotate(&self) -> io::Result<File> {
+        if self.keep == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            for n in (1..self.keep).rev() {
+                let from = backup_path(&self.path, n);
+                if from.is_file() {
+                    fs::rename(&from, backup_path(&self.path, n + 1))?;
+                }
+            }
+            fs::rename(&self.path, backup_path(&self.path, 1))?;
+        }
+        OpenOptions::new().create(true).append(true).open(&self.path)
+    }
+
+    pub fn write(&self, line: &str) {
+        let mut state = self.file.lock().unwrap();
+        let len = line.len() as u64;
+        if state.1 > 0 && state.1 + len > self.max_size {
+            match self.rotate() {
+                Ok(f) => *state = (f, 0),
+                // The logger can't log its own problems
+                Err(e) => eprintln!("Could not rotate {}: {}", self.path.display(), e),
+            }
+        }
+        match state.0.write_all(line.as_bytes()) {
+            Ok(()) => state.1 += len,
+            Err(e) => eprintln!("Could not write to {}: {}", self.path.display(), e),
+        }
+    }
+}
+
+/// Seconds since the epoch, with milliseconds.
+fn timestamp() -> String {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(d) => format!("{}.{:03}", d.as_secs(), d.subsec_nanos() / 1_000_000),
+        Err(_) => "0.000".to_owned(),
+    }
+}
+
+struct Logger {
+    filter: LogFilter,
+    term: Option<Box<TermLogger>>,
+    file: Option<RotatingFile>,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &LogMetadata) -> bool {
+        metadata.level() <= self.filter.level(metadata.target())
+    }
+
+    fn log(&self, record: &LogRecord) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        match self.term {
+            Some(ref term) => term.log(record),
+            None => eprintln!("[{}] {}", record.level(), record.args()),
+        }
+        if let Some(ref file) = self.file {
+            file.write(&format!(
+                "{} {:5} {}: {}\n",
+                timestamp(),
+                record.level(),
+                record.target(),
+                record.args(),
+            ));
+        }
+    }
+}
+
+/// Start logging to the terminal, and to `file` if there is one.
+pub fn init(filter: LogFilter, file: Option<RotatingFile>) -> Result<(), SetLoggerError> {
+    log::set_logger(|max| {
+        max.set(filter.max());
+        Box::new(Logger {
+            // Messages are filtered before they reach the terminal
+            term: TermLogger::new(LogLevelFilter::Trace, Config::default()),
+            filter,
+            file,
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn own(module: &str) -> String {
+        format!("{}::{}", crate_name(), module)
+    }
+
+    #[test]
+    fn default_level() {
+        let filter = LogFilter::from_str("").unwrap();
+        assert_eq!(filter.default, LogLevelFilter::Info);
+        let filter = LogFilter::from_str(" warn ").unwrap();
+        assert_eq!(filter.level(&own("main")), LogLevelFilter::Warn);
+        assert_eq!(filter.level("gfx_device_gl"), LogLevelFilter::Warn);
+    }
+
+    #[test]
+    fn aliases() {
+        let filter = LogFilter::from_str("warn,interact=debug,apps=trace").unwrap();
+        assert_eq!(filter.level(&own("common::gurus::interact")), LogLevelFilter::Debug);
+        assert_eq!(filter.level(&own("app::snowflakes")), LogLevelFilter::Trace);
+        assert_eq!(filter.level(&own("common::gurus::physics")), LogLevelFilter::Warn);
+    }
+
+    #[test]
+    fn most_specific_wins() {
+        let filter = LogFilter::from_str("info,app=warn,app::halo=trace").unwrap();
+        assert_eq!(filter.level(&own("app::halo")), LogLevelFilter::Trace);
+        assert_eq!(filter.level(&own("app::halo::ring")), LogLevelFilter::Trace);
+        assert_eq!(filter.level(&own("app::settings")), LogLevelFilter::Warn);
+        // Only whole path segments match
+        assert_eq!(filter.level(&own("application")), LogLevelFilter::Info);
+    }
+
+    #[test]
+    fn other_crates() {
+        let filter = LogFilter::from_str("warn,gfx_device_gl=error").unwrap();
+        assert_eq!(filter.level("gfx_device_gl::factory"), LogLevelFilter::Error);
+        assert_eq!(filter.level("gfx"), LogLevelFilter::Warn);
+        // Module paths of this crate may also be written in full
+        let filter = LogFilter::from_str(&format!("warn,{}=debug", own("scene"))).unwrap();
+        assert_eq!(filter.level(&own("scene")), LogLevelFilter::Debug);
+    }
+
+    #[test]
+    fn invalid_levels() {
+        assert!(LogFilter::from_str("loud").is_err());
+        assert!(LogFilter::from_str("info,interact=loud").is_err());
+    }
+
+    #[test]
+    fn max_level() {
+        let filter = LogFilter::from_str("warn,physics=debug,state=error").unwrap();
+        assert_eq!(filter.max(), LogLevelFilter::Debug);
+        let filter = LogFilter::from_str("trace,physics=off").unwrap();
+        assert_eq!(filter.max(), LogLevelFilter::Trace);
+    }
+}
@@ -6,6 +6,8 @@ extern crate log;
 extern crate simplelog;
 extern crate clap;
 extern crate ctrlc;
+#[macro_use]
+extern crate lazy_static;
 
 extern crate flight;
 
@@ -14,6 +16,7 @@ extern crate ncollide;
 extern crate nphysics3d;
 
 extern crate glutin;
+#[macro_use]
 extern crate gfx;
 extern crate gfx_device_gl;
 extern crate gfx_window_glutin;
@@ -22,19 +25,25 @@ extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 extern crate serde_json;
+extern crate serde_cbor;
+extern crate ron;
+extern crate toml;
+
+extern crate rusttype;
+extern crate libloading;
+extern crate image;
 
-use std::fs::{self, File};
-use std::io::BufReader;
+use std::fs;
 use std::boxed::FnBox;
 use std::collections::HashMap;
-use std::collections::hash_map::Entry;
-use std::time::Instant;
-use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::process;
 
-use simplelog::{Config, TermLogger, LogLevelFilter};
-use clap::Arg;
+use log::LogLevelFilter;
+use clap::{Arg, SubCommand};
 
 use gfx::{handle, Factory, texture, Device};
 use gfx::format::*;
@@ -44,38 +53,212 @@ use glutin::GlContext;
 
 use nalgebra::{Vector3};
 
-use serde_json::{Serializer, Deserializer};
-use serde_json::de::{IoRead};
-
-use flight::{draw, load};
+use flight::draw;
 use flight::vr::*;
 
+#[macro_use]
+pub mod logging;
 pub mod app;
 pub mod common;
+pub mod config;
 pub mod geo;
+pub mod plugin;
+pub mod prefilter;
+pub mod profile;
+pub mod scene;
+pub mod text;
 pub mod ui;
 
 // use app::{App, halo, home, lets_get_physical, snowflakes, workshop};
-use app::{App, snowflakes, halo, lets_get_physical, settings};
+use app::{AppRegistry, AppInfo, snowflakes, halo, lets_get_physical, settings};
+use app::autosave::Autosaver;
+use app::snapshot::{self, SnapshotMenu, SnapshotAction};
+use app::history::{History, HistoryControls, HistoryAction};
+use scene::SceneDesc;
+use config::Config;
+use logging::LogFilter;
 use common::{Common, Gurus, Meshes, Painters, Meta};
-use common::gurus::{interact, physics};
-
-pub const NEAR_PLANE: f64 = 0.1;
-pub const FAR_PLANE: f64 = 75.;
-pub const MAX_STEP: f64 = 0.02;
+use common::environment::{EnvironmentLoader, open_environment};
+use common::assets::{self, AssetWatcher};
+use common::manifest::{AssetManifest, MANIFEST_PATH};
+use common::settings::{SettingsStore, define_shell};
+use common::gurus::{interact, physics, menu, history};
+use profile::Profiler;
+use ui::{WristMenu, ProfilerHud};
+
+/// Seconds between saves of every app state.
+pub const AUTOSAVE_INTERVAL: u64 = 60;
+/// Previous saves kept for each app state.
+pub const STATE_BACKUPS: usize = 3;
+/// Changes that can be undone.
+pub const HISTORY_LIMIT: usize = 50;
+/// Where frame traces are saved from the wrist menu, unless configured.
+pub const TRACE_PATH: &str = "trace.json";
 
 fn main() {
-    // Logging setup
-    TermLogger::init(LogLevelFilter::Info, Config::default()).unwrap();
-
     // Command line arguments
     let matches = clap::App::new("VR")
-        .arg(Arg::with_name("mock")
-             .short("m")
-             .long("mock")
-             .help("Use mock VR API"))
+        .args(&config::args())
+        .arg(Arg::with_name("scene")
+             .short("s")
+             .long("scene")
+             .takes_value(true)
+             .value_name("FILE")
+             .help("Load a scene file"))
+        .arg(Arg::with_name("load-snapshot")
+             .long("load-snapshot")
+             .takes_value(true)
+             .value_name("NAME")
+             .help("Load a named snapshot after the saved states"))
+        .arg(Arg::with_name("save-snapshot")
+             .long("save-snapshot")
+             .takes_value(true)
+             .value_name("NAME")
+             .validator(|name| if snapshot::valid_name(&name) {
+                 Ok(())
+             } else {
+                 Err("snapshot names may only hold letters, numbers, - and _".to_owned())
+             })
+             .help("Save a named snapshot when exiting"))
+        .subcommand(SubCommand::with_name("snapshots")
+             .about("Manage named snapshots")
+             .subcommand(SubCommand::with_name("list")
+                  .about("List saved snapshots"))
+             .subcommand(SubCommand::with_name("delete")
+                  .about("Delete a snapshot")
+                  .arg(Arg::with_name("NAME").required(true))))
+        .subcommand(SubCommand::with_name("check-assets")
+             .about("Check that every asset in the manifest exists and can be loaded"))
+        .subcommand(SubCommand::with_name("prefilter-env")
+             .about("Prefilter an HDR environment into radiance and irradiance cubemaps")
+             .arg(Arg::with_name("INPUT")
+                  .required(true)
+                  .help("An equirectangular .hdr file, or a directory of posx.hdr, negx.hdr, ... faces"))
+             .arg(Arg::with_name("OUTPUT")
+                  .required(true)
+                  .help("Directory to write the cubemaps to"))
+             .arg(Arg::with_name("size")
+                  .long("size")
+                  .takes_value(true)
+                  .default_value("256")
+                  .validator(prefilter::validate_count::<usize>)
+                  .help("Size of the first radiance level"))
+             .arg(Arg::with_name("levels")
+                  .long("levels")
+                  .takes_value(true)
+                  .default_value("6")
+                  .validator(prefilter::validate_count::<u8>)
+                  .help("Number of radiance levels"))
+             .arg(Arg::with_name("irradiance-size")
+                  .long("irradiance-size")
+                  .takes_value(true)
+                  .default_value("32")
+                  .validator(prefilter::validate_count::<usize>)
+                  .help("Size of the irradiance map"))
+             .arg(Arg::with_name("samples")
+                  .long("samples")
+                  .takes_value(true)
+                  .default_value("256")
+                  .validator(prefilter::validate_count::<u32>)
+                  .help("Samples taken per texel")))
         .get_matches();
-    let mock = matches.is_present("mock");
+
+    // Offline environment prefiltering
+    // Runs without a config, so broken options can't get in the way
+    if let Some(m) = matches.subcommand_matches("prefilter-env") {
+        let filter = LogFilter { default: LogLevelFilter::Info, modules: Vec::new() };
+        logging::init(filter, None).unwrap();
+        // Checked by the validators
+        let mut opts = prefilter::PrefilterOptions {
+            size: m.value_of("size").unwrap().parse().unwrap(),
+            radiance_levels: m.value_of("levels").unwrap().parse().unwrap(),
+            irradiance_size: m.value_of("irradiance-size").unwrap().parse().unwrap(),
+            samples: m.value_of("samples").unwrap().parse().unwrap(),
+        };
+        let max_levels = prefilter::max_levels(opts.size);
+        if opts.radiance_levels > max_levels {
+            warn!("A size of {} only has room for {} radiance levels", opts.size, max_levels);
+            opts.radiance_levels = max_levels;
+        }
+        let input = m.value_of("INPUT").unwrap();
+        let output = m.value_of("OUTPUT").unwrap();
+        let source = match prefilter::Source::open(input) {
+            Ok(s) => s,
+            Err(e) => {
+                error!("Could not read environment {}: {}", input, e);
+                process::exit(1);
+            },
+        };
+        if let Err(e) = prefilter::prefilter(&source, output, &opts) {
+            error!("Could not write environment: {}", e);
+            process::exit(1);
+        }
+        println!("Wrote {} radiance levels to {}, add it to {} to use it", opts.radiance_levels, output, MANIFEST_PATH);
+        return;
+    }
+
+    // Defaults, then the config file, then the environment, then the flags
+    let config = match Config::load(&matches) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("Invalid configuration: {}", e);
+            process::exit(2);
+        },
+    };
+    let mock = config.mock;
+    let state_format = config.state_format;
+    let snapshot_dir = snapshot::snapshot_dir(&config.states_dir);
+
+    // Logging setup
+    let log_file = config.log_file.as_ref().and_then(|path| {
+        match logging::RotatingFile::open(path, config.log_file_size, config.log_files_kept) {
+            Ok(f) => Some(f),
+            Err(e) => {
+                eprintln!("Could not open log file {}: {}", path.display(), e);
+                None
+            },
+        }
+    });
+    logging::init(config.log_filter().unwrap(), log_file).unwrap();
+    assets::set_root(&config.asset_root);
+
+    // Snapshot management
+    if let Some(m) = matches.subcommand_matches("snapshots") {
+        if let Some(m) = m.subcommand_matches("delete") {
+            let name = m.value_of("NAME").unwrap();
+            if let Err(e) = snapshot::delete(&snapshot_dir, name) {
+                error!("Could not delete snapshot {}: {}", name, e);
+                process::exit(1);
+            }
+            println!("Deleted {}", name);
+        } else {
+            for name in snapshot::list(&snapshot_dir) {
+                println!("{}", name);
+            }
+        }
+        return;
+    }
+
+    // Check assets
+    let manifest = match AssetManifest::open(assets::resolve(MANIFEST_PATH)) {
+        Ok(m) => m,
+        Err(e) => {
+            error!("Could not read asset manifest {}: {}", MANIFEST_PATH, e);
+            process::exit(1);
+        },
+    };
+    if matches.subcommand_matches("check-assets").is_some() {
+        let problems = manifest.check();
+        for p in &problems {
+            println!("{}", p);
+        }
+        println!("{} problem(s) found", problems.len());
+        process::exit(if problems.is_empty() { 0 } else { 1 });
+    }
+    // Loading every asset is slow, so only look for missing files
+    for p in &manifest.check_exists() {
+        warn!("{}", p);
+    }
 
     // Handle Ctrl+C
     let running = Arc::new(AtomicBool::new(true));
@@ -94,8 +277,8 @@ fn main() {
     };
 
     // Set clipping planes
-    vrctx.near = NEAR_PLANE;
-    vrctx.far = FAR_PLANE;
+    vrctx.near = config.near_plane;
+    vrctx.far = config.far_plane;
 
     // Get some frame sizeing information
     let (render_width, render_height) = vrctx.retrieve_size();
@@ -135,24 +318,49 @@ fn main() {
     let (.., depth) = factory.create_depth_stencil(render_width as u16, render_height as u16).unwrap();
 
     let surface = factory.view_texture_as_render_target::<(R8_G8_B8_A8, Unorm)>(&tex, 0, None).unwrap();
-    let mut applications: Vec<(_, Box<App<_, _, _, _>>, _)> = vec![
-        ("halo",
-         Box::new(halo::Halo::new(&mut factory).unwrap()),
-         PathBuf::from("states/halo.json")),
-
-        ("lets_get_physical",
-         Box::new(lets_get_physical::LetsGetPhysical::new(&mut factory).unwrap()),
-         PathBuf::from("states/lets_get_physical.json")),
-
-        ("snowflakes",
-         Box::new(snowflakes::Snowflakes::new(&mut factory).unwrap()),
-         PathBuf::from("states/snowflakes.json")),
 
-        // Box::new(workshop::Workshop::new()),
-        ("settings",
-         Box::new(settings::Settings::new()),
-         PathBuf::from("states/settings.json")),
-    ];
+    // Setup Common stuff
+    let mut meshes = Meshes::new(&mut factory).unwrap();
+    let font = text::GlyphAtlas::open(assets::resolve("assets/fonts/DejaVuSansMono.ttf"), 48.)
+        .expect("Could not load font");
+    let mut painters = Painters::new(&mut factory, &font).unwrap();
+
+    let mut registry = AppRegistry::new(&config.states_dir);
+    registry.register(
+        AppInfo { toggleable: false, ..AppInfo::new("halo", "Halo") },
+        halo::Halo::new(&mut factory, &mut meshes).unwrap());
+    // The hammer model isn't in the repository yet, `check-assets` reports it
+    match lets_get_physical::LetsGetPhysical::new(&mut factory, &mut meshes) {
+        Ok(app) => {
+            registry.register(
+                AppInfo { icon: Some(PathBuf::from("assets/hammer/")), ..AppInfo::new("lets_get_physical", "Let's Get Physical") },
+                app);
+        },
+        Err(e) => error!("Could not load Let's Get Physical: {:?}", e),
+    }
+    registry
+        .register(
+            AppInfo { icon: Some(PathBuf::from("assets/snowman/")), ..AppInfo::new("snowflakes", "Snowflakes") },
+            snowflakes::Snowflakes::new(&mut factory, &mut meshes).unwrap())
+        .register(
+            AppInfo { toggleable: false, ..AppInfo::new("settings", "Settings") },
+            settings::Settings::new());
+    if let Some(path) = matches.value_of("scene") {
+        // Each scene file saves its own state
+        let stem = Path::new(path).file_stem().unwrap_or_default().to_string_lossy().into_owned();
+        match SceneDesc::open(path) {
+            Ok(desc) => match app::scene::Scene::new(&mut factory, &mut meshes, desc) {
+                Ok(scene) => {
+                    registry.register(AppInfo::new(format!("scene_{}", stem), stem), scene);
+                },
+                Err(e) => error!("Could not load scene {}: {:?}", path, e),
+            },
+            Err(e) => error!("Could not open scene {}: {}", path, e),
+        }
+    }
+    for (info, plugin) in plugin::discover("plugins") {
+        registry.register(info, plugin);
+    }
 
     // setup context
     let mut ctx = draw::DrawParams {
@@ -164,58 +372,97 @@ fn main() {
     };
 
     let mut meta = Meta {
-        physics_speed: 1.,
-        active_apps: HashMap::new(),
+        settings: SettingsStore::new(),
+        apps: registry.infos(),
+        environments: manifest.environments.iter().map(|e| e.name.clone()).collect(),
+        pulses: Vec::new(),
     };
-    meta.active_apps.insert("halo".to_owned(), true);
-    meta.active_apps.insert("lets_get_physical".to_owned(), true);
-    meta.active_apps.insert("snowflakes".to_owned(), true);
-    meta.active_apps.insert("settings".to_owned(), true);
-
-    // Load from the applications
-    fs::create_dir_all("states").unwrap();
-    for app in applications.iter_mut() {
-        if let Ok(mut file) = File::open(app.2.clone()) {
-            let mut deserializer = Deserializer::new(IoRead::new(file));
-            app.1.de_state(&mut deserializer, &mut meta).unwrap();
+    define_shell(&mut meta.settings, &meta.environments);
+    registry.define_settings(&mut meta.settings, &meta.environments);
+
+    // Load the settings, then the applications
+    meta.settings.open(&config.states_dir, state_format, STATE_BACKUPS);
+    fs::create_dir_all(&config.states_dir).unwrap();
+    registry.load_states(&mut meta, state_format, STATE_BACKUPS);
+    if let Some(name) = matches.value_of("load-snapshot") {
+        match snapshot::open(&snapshot_dir, name) {
+            Ok(snap) => {
+                registry.restore(&snap, &mut meta);
+                info!("Loaded snapshot {}", name);
+            },
+            Err(e) => error!("Could not load snapshot {}: {}", name, e),
         }
     }
+    if let Some(ref ids) = config.apps {
+        for id in ids {
+            if registry.get(id).is_none() {
+                warn!("Unknown app {} in config", id);
+            }
+        }
+        for r in registry.iter() {
+            meta.set_active(&r.info.id, ids.contains(&r.info.id));
+        }
+    }
+
+    // Activate the apps that start active
+    let mut previous_active = HashMap::new();
+    registry.sync_active(&mut meta, &mut previous_active);
+    let mut suspended = false;
+
+    // Report the loaded settings as changed during the first frame
+    meta.settings.end_frame();
 
     if mock { window.show() }
 
     // Setup Controllers
     let mut primary = MappedController::new(primary());
-    primary.max_step = MAX_STEP;
+    primary.max_step = config.max_step;
     let mut secondary = MappedController::new(secondary());
-    secondary.max_step = MAX_STEP;
+    secondary.max_step = config.max_step;
+
+    // Load app icons
+    for info in registry.iter().map(|r| &r.info) {
+        if let Some(ref dir) = info.icon {
+            match meshes.load_object(&mut factory, dir) {
+                Ok(()) => { meshes.icons.insert(info.id.clone(), dir.clone()); },
+                Err(e) => warn!("Could not load icon for {}: {:?}", info.id, e),
+            }
+        }
+    }
 
-    // Setup Common stuff
-    let mut meshes = Meshes::new(&mut factory).unwrap();
-    let mut painters = Painters::new(&mut factory).unwrap();
-
-    // Configure env map
-    let radiance_levels = 6;
-    let radiance = load::load_hdr_cubemap(&mut factory, radiance_levels, |side, level| {
-        let path = format!("assets/snowfield_env/radiance_{}_{}.hdr", level, side);
-        Ok(BufReader::new(File::open(path)?))
-    }).expect("Could not load radiance map");
-    let irradiance = load::load_hdr_cubemap(&mut factory, 1, |side, _| {
-        let path = format!("assets/snowfield_env/irradiance_{}.hdr", side);
-        Ok(BufReader::new(File::open(path)?))
-    }).expect("Could not load irradiance map");
-    painters.uber.cfg(|s| {
-        let env = s.mut_env();
-        env.radiance = radiance;
-        env.radiance_levels = radiance_levels;
-        env.irradiance = irradiance;
-        env.sun_included = false;
-        env.sun_color = [1., 1., 1., 0.];
-    });
+    // Environment maps are read in the background when they are chosen
+    let mut requested_environment: Option<String> = None;
+    let mut environment_loader = EnvironmentLoader::new();
+
+    // Reload assets when they change
+    let mut watcher = AssetWatcher::new(Duration::from_millis(500));
+    meshes.watch(&mut watcher);
+    for env in &manifest.environments {
+        watcher.watch(env.path.clone());
+    }
+
+    let mut wrist_menu = WristMenu::new();
+    let mut snapshot_menu = SnapshotMenu::new(snapshot_dir.clone());
+
+    // Undo and redo changes to the world
+    let mut history = History::new(HISTORY_LIMIT);
+    history.reset(registry.snapshot(&mut meta));
+    let mut history_controls = HistoryControls::new();
+
+    // Save states periodically, in case of a crash
+    let mut autosaver = Autosaver::new(Duration::from_secs(AUTOSAVE_INTERVAL), STATE_BACKUPS);
+
+    // Time each phase of every frame
+    let mut profiler = Profiler::new(config.trace_frames);
+    let mut profiler_hud = ProfilerHud::new(config.profiler_hud);
+    let trace_path = config.trace_file.clone().unwrap_or_else(|| PathBuf::from(TRACE_PATH));
 
     // Main loop
     vrctx.start();
     let mut last_time: Option<Instant> = None;
     while running.load(Ordering::SeqCst) {
+        profiler.end_frame();
+
         // Calculate dt
         let dt = if let Some(last) = last_time {
             let elapsed = last.elapsed();
@@ -225,12 +472,48 @@ fn main() {
         };
         last_time = Some(Instant::now());
 
+        // Switch environment maps once their files have been read
+        if requested_environment.is_none() || meta.environment_changed() {
+            let environment = meta.environment();
+            if requested_environment.as_ref() != Some(&environment) {
+                match manifest.environment(&environment) {
+                    Some(env) => environment_loader.request(env),
+                    None => warn!("Unknown environment {}", environment),
+                }
+                // Don't retry a broken environment every frame
+                requested_environment = Some(environment);
+            }
+        }
+        if let Some((name, files)) = environment_loader.poll() {
+            match files {
+                Ok(files) => match open_environment(&mut factory, &mut painters.uber, &files) {
+                    Ok(()) => info!("Loaded environment {}", name),
+                    Err(e) => warn!("Could not load environment {}: {:?}", name, e),
+                },
+                Err(e) => warn!("Could not read environment {}: {}", name, e),
+            }
+        }
+
+        let timer = profiler.start();
         let moment = vrctx.sync();
+        profiler.end("sync", timer);
 
         let hmd = match moment.hmd() {
             Some(h) => h.clone(),
-            None => continue,
+            None => {
+                if !suspended {
+                    info!("Headset lost, suspending");
+                    registry.suspend(&mut meta);
+                    suspended = true;
+                }
+                continue
+            },
         };
+        if suspended {
+            info!("Headset found, resuming");
+            registry.resume(&mut meta);
+            suspended = false;
+        }
 
         // Update controllers
         match (primary.update(&moment), secondary.update(&moment)) {
@@ -248,51 +531,167 @@ fn main() {
             draw_params: ctx,
             gurus: Gurus {
                 interact: interact::InteractGuru::new(&primary, &secondary, dt),
-                physics: physics::PhysicsGuru::new(Vector3::new(0., -5., 0.)),
+                physics: physics::PhysicsGuru::new(Vector3::new(
+                    config.gravity[0],
+                    config.gravity[1],
+                    config.gravity[2],
+                )),
+                menu: menu::MenuGuru::new(wrist_menu.take_chosen()),
+                history: history::HistoryGuru::new(),
             },
             meshes,
             painters,
             meta,
         };
 
+        let snapshot_action = snapshot_menu.update(&mut common.gurus.menu);
+        let history_action = history_controls.update(
+            &history,
+            &mut common.gurus.menu,
+            &mut common.gurus.interact,
+            &primary,
+            &secondary,
+        );
+        let hud_label = if profiler_hud.visible { "Hide performance" } else { "Show performance" };
+        if common.gurus.menu.entry("profiler/hud", hud_label) {
+            profiler_hud.visible = !profiler_hud.visible;
+        }
+        let save_trace = common.gurus.menu.entry("profiler/trace", "Save frame trace");
+
         // Clear targets
-        common.draw_params.encoder.clear_depth(&common.draw_params.depth, FAR_PLANE as f32);
+        common.draw_params.encoder.clear_depth(&common.draw_params.depth, config.far_plane as f32);
         common.painters.uber.clear_env(&mut common.draw_params);
         
         // Resolve Gurus
         // Draw frame
         let mut common_reply;
         {
-            let mut active_apps = common.meta.active_apps.clone();
-            let futures: Vec<_> = applications.iter_mut()
-                .filter(|app| match active_apps.entry(app.0.to_owned()){
-                    Entry::Occupied(e) => *e.get(),
-                    _ => false,
+            let active: Vec<bool> = registry.iter()
+                .map(|app| common.meta.is_active(&app.info.id))
+                .collect();
+            let futures: Vec<_> = registry.iter_mut()
+                .zip(active)
+                .filter(|&(_, active)| active)
+                .map(|(app, _)| {
+                    let timer = profiler.start();
+                    let f = app.app.update(&mut common);
+                    profiler.end(format!("app/{}/update", app.info.id), timer);
+                    (app.info.id.clone(), f)
                 })
-                .map(|app| app.1.update(&mut common)).collect();
-            let speed = common.meta.physics_speed;
-            common_reply = common.resolve((dt * speed as f64).min(MAX_STEP) as f32);
-            for f in futures {
+                .collect();
+            let timer = profiler.start();
+            let menu = wrist_menu.update(&mut common);
+            profiler.end("wrist_menu", timer);
+            let speed = common.meta.physics_speed();
+            let timer = profiler.start();
+            common_reply = common.resolve((dt * speed as f64).min(config.max_step) as f32);
+            profiler.end("resolve", timer);
+            profiler.record(
+                "physics",
+                common_reply.reply.physics.step_start,
+                common_reply.reply.physics.step_time,
+            );
+            for (id, f) in futures {
+                let timer = profiler.start();
                 FnBox::call_box(f, (&mut common_reply, ));
+                profiler.end(format!("app/{}/reply", id), timer);
             }
+            menu(&mut common_reply);
+            profiler_hud.draw(&mut common_reply, &profiler);
         }
 
         ctx = common_reply.draw_params;
         meshes = common_reply.meshes;
         painters = common_reply.painters;
         meta = common_reply.meta;
+        let mut changes = common_reply.reply.history.changes;
+
+        // rust-webvr doesn't expose haptic actuators yet, so requested pulses
+        // can only be logged
+        for pulse in meta.pulses.drain(..) {
+            trace!("Haptic pulse: {:?}", pulse);
+        }
+
+        // Save, load or delete snapshots from the wrist menu
+        if let Some(action) = snapshot_action {
+            match action {
+                SnapshotAction::Save(name) => {
+                    let snap = registry.snapshot(&mut meta);
+                    match snapshot::save(&snapshot_dir, &name, &snap, state_format) {
+                        Ok(()) => info!("Saved snapshot {}", name),
+                        Err(e) => error!("Could not save snapshot {}: {}", name, e),
+                    }
+                },
+                SnapshotAction::Load(name) => match snapshot::open(&snapshot_dir, &name) {
+                    Ok(snap) => {
+                        registry.restore(&snap, &mut meta);
+                        info!("Loaded snapshot {}", name);
+                        changes.push(format!("load {}", name));
+                    },
+                    Err(e) => error!("Could not load snapshot {}: {}", name, e),
+                },
+                SnapshotAction::Delete(name) => match snapshot::delete(&snapshot_dir, &name) {
+                    Ok(()) => info!("Deleted snapshot {}", name),
+                    Err(e) => error!("Could not delete snapshot {}: {}", name, e),
+                },
+            }
+            snapshot_menu.refresh();
+        }
+
+        // Undo or redo, otherwise remember what changed this frame
+        let restored = match history_action {
+            Some(HistoryAction::Undo) => history.undo(),
+            Some(HistoryAction::Redo) => history.redo(),
+            None => None,
+        }.map(|snap| registry.restore(snap, &mut meta)).is_some();
+        if !restored && !changes.is_empty() {
+            history.record(changes.join(", "), registry.snapshot(&mut meta));
+        }
+
+        if save_trace {
+            match profiler.write_trace(&trace_path) {
+                Ok(()) => info!("Saved frame trace to {}", trace_path.display()),
+                Err(e) => error!("Could not save frame trace to {}: {}", trace_path.display(), e),
+            }
+        }
+
+        // Notify apps that were turned on or off
+        registry.sync_active(&mut meta, &mut previous_active);
+        meta.settings.end_frame();
 
         // Send instructions to OpenGL
         // TODO: Move flush to separate thread
+        let timer = profiler.start();
         ctx.encoder.flush(&mut device);
+        profiler.end("flush", timer);
 
         // Send resulting texture to VR device
+        let timer = profiler.start();
         moment.submit(&mut vrctx);
         if mock { window.swap_buffers().unwrap() }
+        profiler.end("submit", timer);
 
         // Cleanup GFX data
         device.cleanup();
 
+        // Reload changed assets
+        let changed = watcher.changed();
+        if !changed.is_empty() {
+            meshes.reload(&mut factory, &changed);
+            if manifest.environment(&meta.environment()).map_or(false, |e| changed.contains(&e.path)) {
+                requested_environment = None;
+            }
+        }
+
+        // Autosave
+        if autosaver.due() {
+            let timer = profiler.start();
+            let mut states = registry.save_states(&mut meta, state_format);
+            states.extend(meta.settings.saved(&config.states_dir, state_format));
+            autosaver.save(states);
+            profiler.end("autosave", timer);
+        }
+
         // Window Events
         events_loop.poll_events(|event| {
             match event {
@@ -305,9 +704,22 @@ fn main() {
     }
     vrctx.stop();
 
-    for app in applications.iter_mut() {
-        let mut file = File::create(&app.2).unwrap();
-        let mut serializer = Serializer::new(file);
-        app.1.se_state(&mut serializer, &mut meta).unwrap();
+    if config.trace_file.is_some() {
+        if let Err(e) = profiler.write_trace(&trace_path) {
+            error!("Could not save frame trace to {}: {}", trace_path.display(), e);
+        }
+    }
+
+    registry.shutdown(&mut meta);
+    if let Some(name) = matches.value_of("save-snapshot") {
+        let snap = registry.snapshot(&mut meta);
+        match snapshot::save(&snapshot_dir, name, &snap, state_format) {
+            Ok(()) => info!("Saved snapshot {}", name),
+            Err(e) => error!("Could not save snapshot {}: {}", name, e),
+        }
     }
+    let mut states = registry.save_states(&mut meta, state_format);
+    states.extend(meta.settings.saved(&config.states_dir, state_format));
+    autosaver.save(states);
+    autosaver.finish();
 }
@@ -0,0 +1,345 @@
+//! Apps loaded from shared libraries at runtime.
+//!
+//! A plugin is a `cdylib` that exports a static `PluginVTable` named
+//! `vr_plugin_vtable`. Only C types cross the boundary, so plugins can be
+//! built separately from the shell (and by a different compiler version), as
+//! long as `abi_version` matches `PLUGIN_ABI_VERSION`.
+//!
+//! Plugins are loaded from a copy of the library file, so the original can be
+//! rebuilt while the shell is running. When it changes, the plugin's state is
+//! saved, the new library is loaded, and the state is restored.
+
+use std::env;
+use std::fs;
+use std::io;
+use std::boxed::FnBox;
+use std::os::raw::c_void;
+use std::path::{Path, PathBuf};
+use std::slice;
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+use std::time::{Instant, SystemTime, Duration};
+
+use libloading::Library;
+
+use serde_json::{Error as JsonError, Value};
+
+use nalgebra::{Matrix4, Transform3};
+
+use gfx;
+use app::{App, AppInfo};
+use app::state::{self, VersionedState};
+use common::{Common, CommonReply, Meta};
+use common::gurus::interact::ControllerGuru;
+
+/// Must match `PluginVTable::abi_version` for a plugin to be loaded.
+pub const PLUGIN_ABI_VERSION: u32 = 1;
+
+/// How often plugin files are checked for changes.
+const RELOAD_CHECK: u64 = 1;
+
+/// The state of a controller, as seen by a plugin.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct PluginController {
+    /// Column-major pose matrix.
+    pub pose: [f32; 16],
+    pub trigger: f32,
+    /// Non-zero while the menu button is held.
+    pub menu: u8,
+}
+
+/// Everything a plugin is told each frame.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct PluginFrame {
+    pub dt: f64,
+    pub primary: PluginController,
+    pub secondary: PluginController,
+}
+
+/// Meshes built into the shell that plugins can draw.
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PluginMesh {
+    WireBox = 0,
+    Button = 1,
+    ButtonActive = 2,
+    Controller = 3,
+}
+
+/// A request from a plugin to draw a mesh.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct PluginDraw {
+    /// One of the `PluginMesh` values.
+    pub mesh: u32,
+    /// Column-major model matrix.
+    pub transform: [f32; 16],
+}
+
+/// Called by a plugin to draw something. `ctx` must be the pointer passed to
+/// the plugin alongside this function.
+pub type DrawFn = extern "C" fn(ctx: *mut c_void, draw: *const PluginDraw);
+
+/// Called by a plugin to write saved state. `ctx` must be the pointer passed
+/// to the plugin alongside this function.
+pub type WriteFn = extern "C" fn(ctx: *mut c_void, data: *const u8, len: usize);
+
+/// Functions exported by a plugin.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct PluginVTable {
+    pub abi_version: u32,
+    /// Create a new instance of the plugin.
+    pub create: extern "C" fn() -> *mut c_void,
+    /// Destroy an instance created by `create`.
+    pub destroy: extern "C" fn(instance: *mut c_void),
+    /// Update the instance and draw it by calling `draw`.
+    pub update: extern "C" fn(
+        instance: *mut c_void,
+        frame: *const PluginFrame,
+        draw: DrawFn,
+        draw_ctx: *mut c_void,
+    ),
+    /// Save the state of the instance by calling `write` any number of times.
+    pub save: extern "C" fn(instance: *mut c_void, write: WriteFn, write_ctx: *mut c_void),
+    /// Restore state written by `save`.
+    pub load: extern "C" fn(instance: *mut c_void, data: *const u8, len: usize),
+}
+
+extern "C" fn push_draw(ctx: *mut c_void, draw: *const PluginDraw) {
+    unsafe { (*(ctx as *mut Vec<PluginDraw>)).push(*draw) }
+}
+
+extern "C" fn push_bytes(ctx: *mut c_void, data: *const u8, len: usize) {
+    unsafe { (*(ctx as *mut Vec<u8>)).extend_from_slice(slice::from_raw_parts(data, len)) }
+}
+
+fn matrix(pose: Matrix4<f32>) -> [f32; 16] {
+    let mut out = [0.; 16];
+    out.copy_from_slice(pose.as_slice());
+    out
+}
+
+/// A loaded library and an instance of its plugin.
+struct Loaded {
+    vtable: PluginVTable,
+    instance: *mut c_void,
+    // Must be dropped after the instance is destroyed
+    _lib: Library,
+    copy: PathBuf,
+}
+
+impl Loaded {
+    fn open(path: &Path) -> io::Result<Loaded> {
+        // Load from a uniquely named copy, so that the original can be
+        // replaced and reloaded
+        static COPIES: AtomicUsize = ATOMIC_USIZE_INIT;
+        let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        let copy = env::temp_dir().join(format!(
+            "vr-plugin-{}-{}-{}",
+            ::std::process::id(),
+            COPIES.fetch_add(1, Ordering::SeqCst),
+            name,
+        ));
+        fs::copy(path, &copy)?;
+
+        match Loaded::open_copy(&copy) {
+            Ok((vtable, instance, lib)) => Ok(Loaded { vtable, instance, _lib: lib, copy }),
+            Err(e) => {
+                let _ = fs::remove_file(&copy);
+                Err(e)
+            },
+        }
+    }
+
+    /// Load the library and create an instance of its plugin.
+    fn open_copy(copy: &Path) -> io::Result<(PluginVTable, *mut c_void, Library)> {
+        let lib = Library::new(copy)?;
+        let vtable = unsafe {
+            let sym = lib.get::<*const PluginVTable>(b"vr_plugin_vtable\0")?;
+            **sym
+        };
+        if vtable.abi_version != PLUGIN_ABI_VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!(
+                "plugin ABI version {} does not match {}",
+                vtable.abi_version,
+                PLUGIN_ABI_VERSION,
+            )));
+        }
+        let instance = (vtable.create)();
+        if instance.is_null() {
+            return Err(io::Error::new(io::ErrorKind::Other, "plugin could not create an instance"));
+        }
+        Ok((vtable, instance, lib))
+    }
+
+    fn save(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        (self.vtable.save)(self.instance, push_bytes, &mut data as *mut Vec<u8> as *mut c_void);
+        data
+    }
+
+    fn load(&mut self, data: &[u8]) {
+        (self.vtable.load)(self.instance, data.as_ptr(), data.len());
+    }
+}
+
+impl Drop for Loaded {
+    fn drop(&mut self) {
+        (self.vtable.destroy)(self.instance);
+    }
+}
+
+/// Whatever the plugin saved.
+#[derive(Serialize, Deserialize)]
+struct PluginState(Vec<u8>);
+
+impl VersionedState for PluginState {
+    const VERSION: u32 = 1;
+}
+
+/// An app backed by a plugin library.
+pub struct PluginApp {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    last_check: Instant,
+    loaded: Option<Loaded>,
+}
+
+impl PluginApp {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<PluginApp> {
+        let path = path.as_ref().to_owned();
+        let modified = fs::metadata(&path)?.modified().ok();
+        let loaded = Loaded::open(&path)?;
+        Ok(PluginApp {
+            path,
+            modified,
+            last_check: Instant::now(),
+            loaded: Some(loaded),
+        })
+    }
+
+    /// Reload the plugin if its file has changed, keeping its state.
+    fn check_reload(&mut self) {
+        if self.last_check.elapsed() < Duration::from_secs(RELOAD_CHECK) {
+            return;
+        }
+        self.last_check = Instant::now();
+
+        let modified = fs::metadata(&self.path).and_then(|m| m.modified()).ok();
+        if modified.is_none() || modified == self.modified {
+            return;
+        }
+        self.modified = modified;
+
+        let state = self.loaded.as_ref().map(|l| l.save());
+        let old_copy = self.loaded.as_ref().map(|l| l.copy.clone());
+        // Unload first, some platforms keep the old code otherwise
+        self.loaded = None;
+        if let Some(copy) = old_copy {
+            let _ = fs::remove_file(copy);
+        }
+        match Loaded::open(&self.path) {
+            Ok(mut l) => {
+                if let Some(state) = state {
+                    l.load(&state);
+                }
+                info!("Reloaded plugin {}", self.path.display());
+                self.loaded = Some(l);
+            },
+            Err(e) => error!("Could not reload plugin {}: {}", self.path.display(), e),
+        }
+    }
+}
+
+impl Drop for PluginApp {
+    fn drop(&mut self) {
+        if let Some(copy) = self.loaded.take().map(|l| l.copy.clone()) {
+            let _ = fs::remove_file(copy);
+        }
+    }
+}
+
+impl<R: gfx::Resources + 'static, C: gfx::CommandBuffer<R> + 'static> App<R, C> for PluginApp {
+    fn se_state(&self, _: &mut Meta) -> Result<Value, JsonError> {
+        let data = self.loaded.as_ref().map(|l| l.save()).unwrap_or_default();
+        state::save(&PluginState(data))
+    }
+
+    fn de_state(&mut self, saved: Value, _: &mut Meta) -> Result<(), JsonError> {
+        let PluginState(data) = state::load(saved)?;
+        if let Some(ref mut l) = self.loaded {
+            l.load(&data);
+        }
+        Ok(())
+    }
+
+    fn update<'b>(&'b mut self,
+                  common: &mut Common<R, C>)
+                  -> Box<FnBox(&mut CommonReply<R, C>) + 'b> {
+        self.check_reload();
+
+        if let Some(ref l) = self.loaded {
+            let controller = |c: &ControllerGuru| PluginController {
+                pose: matrix(c.data.pose.to_homogeneous()),
+                trigger: c.data.trigger as f32,
+                menu: c.data.menu as u8,
+            };
+            let frame = PluginFrame {
+                dt: common.gurus.interact.dt,
+                primary: controller(&common.gurus.interact.primary),
+                secondary: controller(&common.gurus.interact.secondary),
+            };
+
+            let mut draws: Vec<PluginDraw> = Vec::new();
+            (l.vtable.update)(l.instance, &frame, push_draw, &mut draws as *mut Vec<PluginDraw> as *mut c_void);
+
+            for d in draws {
+                let trans = Transform3::from_matrix_unchecked(Matrix4::from_column_slice(&d.transform));
+                let params = &mut common.draw_params;
+                match d.mesh {
+                    m if m == PluginMesh::WireBox as u32 =>
+                        common.painters.solid.draw(params, trans, &common.meshes.wire_box),
+                    m if m == PluginMesh::Button as u32 =>
+                        common.painters.uber.draw(params, trans, &common.meshes.button),
+                    m if m == PluginMesh::ButtonActive as u32 =>
+                        common.painters.uber.draw(params, trans, &common.meshes.button_active),
+                    m if m == PluginMesh::Controller as u32 =>
+                        common.painters.uber.draw(params, trans, &common.meshes.controller),
+                    m => warn!("Plugin {} drew unknown mesh {}", self.path.display(), m),
+                }
+            }
+        }
+
+        Box::new(|_: &mut CommonReply<_, _>| ())
+    }
+}
+
+/// Open every shared library in the given directory as a plugin. Plugins that
+/// fail to load are skipped with an error.
+pub fn discover<P: AsRef<Path>>(dir: P) -> Vec<(AppInfo, PluginApp)> {
+    let entries = match fs::read_dir(dir.as_ref()) {
+        Ok(e) => e,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut plugins = Vec::new();
+    for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+        let is_lib = path.extension()
+            .map(|ext| ext == "so" || ext == "dll" || ext == "dylib")
+            .unwrap_or(false);
+        if !is_lib {
+            continue;
+        }
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+        match PluginApp::open(&path) {
+            Ok(app) => {
+                info!("Loaded plugin {}", path.display());
+                plugins.push((AppInfo::new(format!("plugin_{}", stem), stem), app));
+            },
+            Err(e) => error!("Could not load plugin {}: {}", path.display(), e),
+        }
+    }
+    plugins
+}
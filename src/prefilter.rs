@@ -0,0 +1,369 @@
+//! Prefilters HDR environments into the cubemaps read by `open_environment`.
+//!
+//! The source is either an equirectangular `.hdr` image, or a directory of
+//! cubemap faces named `posx.hdr`, `negx.hdr`, and so on. Radiance levels are
+//! convolved with the GGX distribution at increasing roughness, and the
+//! irradiance map with a cosine lobe.
+
+use std::f32::consts::PI;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use image::ImageError;
+use image::hdr::HDRDecoder;
+use nalgebra::Vector3;
+
+use common::environment::CUBE_SIDES;
+
+/// A floating point RGB image.
+pub struct HdrImage {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Vector3<f32>>,
+}
+
+impl HdrImage {
+    pub fn new(width: usize, height: usize) -> HdrImage {
+        HdrImage { width, height, pixels: vec![Vector3::new(0., 0., 0.); width * height] }
+    }
+
+    fn get(&self, x: usize, y: usize) -> Vector3<f32> {
+        self.pixels[y.min(self.height - 1) * self.width + x.min(self.width - 1)]
+    }
+
+    /// Bilinear sample at a position in `[0, 1]`. Wraps horizontally if
+    /// `wrap` is set, otherwise clamps.
+    fn sample(&self, u: f32, v: f32, wrap: bool) -> Vector3<f32> {
+        let x = u * self.width as f32 - 0.5;
+        let y = (v * self.height as f32 - 0.5).max(0.);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let width = self.width as isize;
+        let column = |x: f32| if wrap {
+            ((x as isize % width + width) % width) as usize
+        } else {
+            x.max(0.) as usize
+        };
+        let (xa, xb) = (column(x0), column(x0 + 1.));
+        let (ya, yb) = (y0 as usize, y0 as usize + 1);
+        let top = self.get(xa, ya) * (1. - fx) + self.get(xb, ya) * fx;
+        let bottom = self.get(xa, yb) * (1. - fx) + self.get(xb, yb) * fx;
+        top * (1. - fy) + bottom * fy
+    }
+}
+
+fn invalid(e: ImageError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+pub fn read_hdr<P: AsRef<Path>>(path: P) -> io::Result<HdrImage> {
+    let decoder = HDRDecoder::new(BufReader::new(File::open(path)?)).map_err(invalid)?;
+    let meta = decoder.metadata();
+    let pixels = decoder.read_image_hdr().map_err(invalid)?;
+    Ok(HdrImage {
+        width: meta.width as usize,
+        height: meta.height as usize,
+        pixels: pixels.into_iter().map(|p| Vector3::new(p.data[0], p.data[1], p.data[2])).collect(),
+    })
+}
+
+/// Encode a color as shared-exponent RGBE.
+fn rgbe(c: Vector3<f32>) -> [u8; 4] {
+    let v = c.x.max(c.y).max(c.z);
+    if v < 1e-32 {
+        return [0, 0, 0, 0];
+    }
+    let e = v.log2().floor() as i32 + 1;
+    let scale = 256. / 2f32.powi(e);
+    [
+        (c.x * scale).min(255.) as u8,
+        (c.y * scale).min(255.) as u8,
+        (c.z * scale).min(255.) as u8,
+        (e + 128) as u8,
+    ]
+}
+
+/// Write an uncompressed Radiance HDR file.
+pub fn write_hdr<P: AsRef<Path>>(path: P, img: &HdrImage) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    write!(out, "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n", img.height, img.width)?;
+    for &p in &img.pixels {
+        out.write_all(&rgbe(p))?;
+    }
+    out.flush()
+}
+
+/// Direction through a point on a cubemap face, with `u` and `v` in `[-1, 1]`
+/// running right and down.
+fn face_dir(side: usize, u: f32, v: f32) -> Vector3<f32> {
+    let d = match side {
+        0 => Vector3::new(1., -v, -u),
+        1 => Vector3::new(-1., -v, u),
+        2 => Vector3::new(u, 1., v),
+        3 => Vector3::new(u, -1., -v),
+        4 => Vector3::new(u, -v, 1.),
+        _ => Vector3::new(-u, -v, -1.),
+    };
+    d.normalize()
+}
+
+/// The face and `[0, 1]` coordinates that a direction points through.
+fn dir_face(d: Vector3<f32>) -> (usize, f32, f32) {
+    let a = Vector3::new(d.x.abs(), d.y.abs(), d.z.abs());
+    let (side, u, v, m) = if a.x >= a.y && a.x >= a.z {
+        if d.x > 0. { (0, -d.z, -d.y, a.x) } else { (1, d.z, -d.y, a.x) }
+    } else if a.y >= a.z {
+        if d.y > 0. { (2, d.x, d.z, a.y) } else { (3, d.x, -d.z, a.y) }
+    } else {
+        if d.z > 0. { (4, d.x, -d.y, a.z) } else { (5, -d.x, -d.y, a.z) }
+    };
+    (side, (u / m + 1.) / 2., (v / m + 1.) / 2.)
+}
+
+/// An environment to prefilter.
+pub enum Source {
+    Equirectangular(HdrImage),
+    Cubemap(Vec<HdrImage>),
+}
+
+impl Source {
+    /// Open an equirectangular image, or a directory of cubemap faces.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Source> {
+        let path = path.as_ref();
+        if path.is_dir() {
+            let mut faces = Vec::with_capacity(6);
+            for side in &CUBE_SIDES {
+                faces.push(read_hdr(path.join(format!("{}.hdr", side)))?);
+            }
+            Ok(Source::Cubemap(faces))
+        } else {
+            Ok(Source::Equirectangular(read_hdr(path)?))
+        }
+    }
+
+    /// The radiance coming from a direction.
+    pub fn sample(&self, d: Vector3<f32>) -> Vector3<f32> {
+        match *self {
+            Source::Equirectangular(ref img) => {
+                let u = 0.5 + d.z.atan2(d.x) / (2. * PI);
+                let v = d.y.max(-1.).min(1.).acos() / PI;
+                img.sample(u, v, true)
+            },
+            Source::Cubemap(ref faces) => {
+                let (side, u, v) = dir_face(d);
+                faces[side].sample(u, v, false)
+            },
+        }
+    }
+}
+
+/// The `i`th of `n` points of the Hammersley sequence.
+fn hammersley(i: u32, n: u32) -> (f32, f32) {
+    let mut bits = i;
+    bits = (bits << 16) | (bits >> 16);
+    bits = ((bits & 0x55555555) << 1) | ((bits & 0xAAAAAAAA) >> 1);
+    bits = ((bits & 0x33333333) << 2) | ((bits & 0xCCCCCCCC) >> 2);
+    bits = ((bits & 0x0F0F0F0F) << 4) | ((bits & 0xF0F0F0F0) >> 4);
+    bits = ((bits & 0x00FF00FF) << 8) | ((bits & 0xFF00FF00) >> 8);
+    (i as f32 / n as f32, bits as f32 / 4294967296.)
+}
+
+/// Two unit vectors perpendicular to `n` and each other.
+fn tangents(n: Vector3<f32>) -> (Vector3<f32>, Vector3<f32>) {
+    let up = if n.y.abs() < 0.999 { Vector3::y() } else { Vector3::x() };
+    let t = up.cross(&n).normalize();
+    (t, n.cross(&t))
+}
+
+/// Radiance reflected toward `n` by a surface with normal `n`, using the
+/// split-sum approximation.
+fn ggx(source: &Source, n: Vector3<f32>, roughness: f32, samples: u32) -> Vector3<f32> {
+    let a = roughness * roughness;
+    let (t, b) = tangents(n);
+    let mut sum = Vector3::new(0., 0., 0.);
+    let mut weight = 0.;
+    for i in 0..samples {
+        let (x, y) = hammersley(i, samples);
+        let phi = 2. * PI * x;
+        let cos_theta = ((1. - y) / (1. + (a * a - 1.) * y)).sqrt();
+        let sin_theta = (1. - cos_theta * cos_theta).sqrt();
+        let h = t * (sin_theta * phi.cos()) + b * (sin_theta * phi.sin()) + n * cos_theta;
+        let l = h * (2. * n.dot(&h)) - n;
+        let n_dot_l = n.dot(&l);
+        if n_dot_l > 0. {
+            sum += source.sample(l) * n_dot_l;
+            weight += n_dot_l;
+        }
+    }
+    if weight > 0. { sum / weight } else { source.sample(n) }
+}
+
+/// Cosine weighted average of the radiance around `n`.
+fn irradiance(source: &Source, n: Vector3<f32>, samples: u32) -> Vector3<f32> {
+    let (t, b) = tangents(n);
+    let mut sum = Vector3::new(0., 0., 0.);
+    for i in 0..samples {
+        let (x, y) = hammersley(i, samples);
+        let phi = 2. * PI * x;
+        let r = y.sqrt();
+        let l = t * (r * phi.cos()) + b * (r * phi.sin()) + n * (1. - y).sqrt();
+        sum += source.sample(l.normalize());
+    }
+    sum / samples as f32
+}
+
+/// Render every face of a cubemap.
+fn render_faces<F: Fn(Vector3<f32>) -> Vector3<f32>>(size: usize, f: F) -> Vec<HdrImage> {
+    (0..6).map(|side| {
+        let mut img = HdrImage::new(size, size);
+        for y in 0..size {
+            for x in 0..size {
+                let u = 2. * (x as f32 + 0.5) / size as f32 - 1.;
+                let v = 2. * (y as f32 + 0.5) / size as f32 - 1.;
+                img.pixels[y * size + x] = f(face_dir(side, u, v));
+            }
+        }
+        img
+    }).collect()
+}
+
+/// Check that a command line argument is a number above zero, for use with
+/// `clap::Arg::validator`.
+pub fn validate_count<T: FromStr + PartialOrd + Default>(text: String) -> Result<(), String> {
+    match text.parse::<T>() {
+        Ok(n) => if n > T::default() {
+            Ok(())
+        } else {
+            Err(format!("{} must be above zero", text))
+        },
+        Err(_) => Err(format!("{} is not a whole number in range", text)),
+    }
+}
+
+/// The most radiance levels a cubemap of `size` can have, halving down to
+/// 1x1.
+pub fn max_levels(size: usize) -> u8 {
+    (usize::max_value().count_ones() - size.leading_zeros()) as u8
+}
+
+pub struct PrefilterOptions {
+    /// Size of the largest radiance level.
+    pub size: usize,
+    pub radiance_levels: u8,
+    pub irradiance_size: usize,
+    /// Samples taken per texel.
+    pub samples: u32,
+}
+
+/// Write `radiance_{level}_{side}.hdr` and `irradiance_{side}.hdr` into
+/// `out`, creating it if needed.
+pub fn prefilter<P: AsRef<Path>>(source: &Source, out: P, opts: &PrefilterOptions) -> io::Result<()> {
+    let out = out.as_ref();
+    fs::create_dir_all(out)?;
+
+    for level in 0..opts.radiance_levels {
+        let size = (opts.size >> level).max(1);
+        let roughness = if opts.radiance_levels > 1 {
+            level as f32 / (opts.radiance_levels - 1) as f32
+        } else {
+            0.
+        };
+        info!("Radiance level {} ({}x{}, roughness {:.2})", level, size, size, roughness);
+        let faces = if level == 0 {
+            render_faces(size, |d| source.sample(d))
+        } else {
+            render_faces(size, |d| ggx(source, d, roughness, opts.samples))
+        };
+        for (side, face) in CUBE_SIDES.iter().zip(faces) {
+            write_hdr(out.join(format!("radiance_{}_{}.hdr", level, side)), &face)?;
+        }
+    }
+
+    info!("Irradiance ({}x{})", opts.irradiance_size, opts.irradiance_size);
+    let faces = render_faces(opts.irradiance_size, |d| irradiance(source, d, opts.samples));
+    for (side, face) in CUBE_SIDES.iter().zip(faces) {
+        write_hdr(out.join(format!("irradiance_{}.hdr", side)), &face)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-5, "{} != {}", a, b);
+    }
+
+    /// Decode a shared-exponent RGBE color.
+    fn from_rgbe(c: [u8; 4]) -> Vector3<f32> {
+        if c[3] == 0 {
+            return Vector3::new(0., 0., 0.);
+        }
+        let scale = 2f32.powi(c[3] as i32 - 128 - 8);
+        Vector3::new(c[0] as f32 * scale, c[1] as f32 * scale, c[2] as f32 * scale)
+    }
+
+    #[test]
+    fn face_round_trip() {
+        for side in 0..6 {
+            for &u in &[-0.9, -0.5, 0., 0.3, 0.9] {
+                for &v in &[-0.9, -0.2, 0., 0.5, 0.9] {
+                    let (s, fu, fv) = dir_face(face_dir(side, u, v));
+                    assert_eq!(s, side, "{} at ({}, {})", CUBE_SIDES[side], u, v);
+                    assert_close(fu, (u + 1.) / 2.);
+                    assert_close(fv, (v + 1.) / 2.);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn faces_point_along_their_axis() {
+        let axes = [
+            Vector3::x(), -Vector3::x(),
+            Vector3::y(), -Vector3::y(),
+            Vector3::z(), -Vector3::z(),
+        ];
+        for (side, axis) in axes.iter().enumerate() {
+            assert_close(face_dir(side, 0., 0.).dot(axis), 1.);
+            assert_eq!(dir_face(*axis).0, side);
+        }
+    }
+
+    #[test]
+    fn rgbe_encoding() {
+        assert_eq!(rgbe(Vector3::new(0., 0., 0.)), [0, 0, 0, 0]);
+        assert_eq!(rgbe(Vector3::new(1., 1., 1.)), [128, 128, 128, 129]);
+        assert_eq!(rgbe(Vector3::new(0.5, 0.25, 0.)), [128, 64, 0, 128]);
+        for &c in &[Vector3::new(3.7, 0.2, 12.), Vector3::new(0.01, 0.02, 0.005), Vector3::new(1000., 1., 0.)] {
+            let max: f32 = c.x.max(c.y).max(c.z);
+            let d = from_rgbe(rgbe(c)) - c;
+            for &e in &[d.x, d.y, d.z] {
+                assert!(e.abs() <= max / 128., "{:?} decoded {} off", c, e);
+            }
+        }
+    }
+
+    #[test]
+    fn hammersley_points() {
+        assert_eq!(hammersley(0, 4), (0., 0.));
+        assert_eq!(hammersley(1, 4), (0.25, 0.5));
+        assert_eq!(hammersley(2, 4), (0.5, 0.25));
+        assert_eq!(hammersley(3, 4), (0.75, 0.75));
+        for i in 0..64 {
+            let (x, y) = hammersley(i, 64);
+            assert!(x >= 0. && x < 1. && y >= 0. && y < 1.);
+        }
+    }
+
+    #[test]
+    fn levels_stop_at_one_texel() {
+        assert_eq!(max_levels(1), 1);
+        assert_eq!(max_levels(2), 2);
+        assert_eq!(max_levels(255), 8);
+        assert_eq!(max_levels(256), 9);
+    }
+}
@@ -0,0 +1,271 @@
+//! Times each phase of a frame.
+//!
+//! Phases are recorded as spans, named like `physics` or `app/snowflakes/update`.
+//! Every span name keeps a histogram of its durations, which is what the
+//! `ProfilerHud` shows. The spans of the last few frames are kept so they can
+//! be written as a Chrome trace, to be opened in `chrome://tracing`.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use serde_json;
+
+fn millis(d: Duration) -> f64 {
+    d.as_secs() as f64 * 1e3 + d.subsec_nanos() as f64 * 1e-6
+}
+
+fn micros(d: Duration) -> f64 {
+    millis(d) * 1e3
+}
+
+/// Histogram buckets, each twice as wide as the last. The first holds up to
+/// `FIRST_BUCKET` microseconds and the last holds everything longer.
+const BUCKETS: usize = 16;
+const FIRST_BUCKET: f64 = 16.;
+/// Weight of each new duration in `Histogram::recent_ms`.
+const RECENT_WEIGHT: f64 = 0.05;
+
+/// Durations recorded for one span name.
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    buckets: [u64; BUCKETS],
+    pub count: u64,
+    max_ms: f64,
+    recent_ms: f64,
+}
+
+impl Histogram {
+    pub fn new() -> Histogram {
+        Histogram {
+            buckets: [0; BUCKETS],
+            count: 0,
+            max_ms: 0.,
+            recent_ms: 0.,
+        }
+    }
+
+    pub fn add(&mut self, duration: Duration) {
+        let ms = millis(duration);
+        let mut bucket = 0;
+        let mut limit = FIRST_BUCKET;
+        while bucket < BUCKETS - 1 && ms * 1e3 > limit {
+            bucket += 1;
+            limit *= 2.;
+        }
+        self.buckets[bucket] += 1;
+        self.recent_ms = if self.count == 0 {
+            ms
+        } else {
+            self.recent_ms * (1. - RECENT_WEIGHT) + ms * RECENT_WEIGHT
+        };
+        self.count += 1;
+        self.max_ms = self.max_ms.max(ms);
+    }
+
+    /// An average weighted towards the latest durations.
+    pub fn recent_ms(&self) -> f64 {
+        self.recent_ms
+    }
+
+    pub fn max_ms(&self) -> f64 {
+        self.max_ms
+    }
+
+    /// The upper bound of the bucket holding the `p`th percentile, where `p`
+    /// is between 0 and 1. The last bucket has no upper bound, so the
+    /// longest duration is used instead.
+    pub fn percentile_ms(&self, p: f64) -> f64 {
+        let target = (p * self.count as f64).ceil() as u64;
+        let mut seen = 0;
+        let mut limit = FIRST_BUCKET;
+        for &n in &self.buckets[..BUCKETS - 1] {
+            seen += n;
+            if seen >= target {
+                return (limit * 1e-3).min(self.max_ms);
+            }
+            limit *= 2.;
+        }
+        self.max_ms
+    }
+}
+
+/// A timed phase of a frame.
+#[derive(Debug, Clone)]
+pub struct Span {
+    pub name: String,
+    /// Time since the profiler was created.
+    pub start: Duration,
+    pub duration: Duration,
+}
+
+/// Returned by `Profiler::start`, and passed back to `Profiler::end`.
+#[must_use]
+pub struct Timer(Instant);
+
+/// An event in the Chrome trace format. Times are in microseconds.
+#[derive(Serialize)]
+struct TraceEvent<'a> {
+    name: &'a str,
+    cat: &'a str,
+    ph: &'a str,
+    ts: f64,
+    dur: f64,
+    pid: u32,
+    tid: u32,
+}
+
+#[derive(Serialize)]
+struct Trace<'a> {
+    #[serde(rename = "traceEvents")]
+    trace_events: Vec<TraceEvent<'a>>,
+}
+
+pub struct Profiler {
+    origin: Instant,
+    frame_start: Option<Instant>,
+    /// Spans of the frame in progress.
+    current: Vec<Span>,
+    /// Spans of the latest finished frames, oldest first.
+    recent: VecDeque<Vec<Span>>,
+    /// How many finished frames are kept for traces.
+    pub trace_frames: usize,
+    histograms: BTreeMap<String, Histogram>,
+}
+
+impl Profiler {
+    pub fn new(trace_frames: usize) -> Profiler {
+        Profiler {
+            origin: Instant::now(),
+            frame_start: None,
+            current: Vec::new(),
+            recent: VecDeque::new(),
+            trace_frames,
+            histograms: BTreeMap::new(),
+        }
+    }
+
+    pub fn start(&self) -> Timer {
+        Timer(Instant::now())
+    }
+
+    /// Record a span from when `timer` was started until now.
+    pub fn end<N: Into<String>>(&mut self, name: N, timer: Timer) {
+        let Timer(start) = timer;
+        self.record(name, start, start.elapsed());
+    }
+
+    /// Record a span timed elsewhere.
+    pub fn record<N: Into<String>>(&mut self, name: N, start: Instant, duration: Duration) {
+        let name = name.into();
+        self.histograms.entry(name.clone())
+            .or_insert_with(Histogram::new)
+            .add(duration);
+        self.current.push(Span {
+            name,
+            start: start.duration_since(self.origin),
+            duration,
+        });
+    }
+
+    /// Finish the frame in progress, recording the time since the last call
+    /// as a `frame` span. Called once per frame, at the same point each time.
+    pub fn end_frame(&mut self) {
+        let now = Instant::now();
+        if let Some(start) = self.frame_start {
+            self.record("frame", start, now.duration_since(start));
+        }
+        self.frame_start = Some(now);
+
+        let spans = ::std::mem::replace(&mut self.current, Vec::new());
+        self.recent.push_back(spans);
+        while self.recent.len() > self.trace_frames {
+            self.recent.pop_front();
+        }
+    }
+
+    pub fn histogram(&self, name: &str) -> Option<&Histogram> {
+        self.histograms.get(name)
+    }
+
+    /// Every histogram, sorted by span name.
+    pub fn histograms<'a>(&'a self) -> impl Iterator<Item = (&'a str, &'a Histogram)> + 'a {
+        self.histograms.iter().map(|(n, h)| (n.as_str(), h))
+    }
+
+    /// Write the spans of the latest frames as a Chrome trace.
+    pub fn write_trace<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let trace = Trace {
+            trace_events: self.recent.iter()
+                .flat_map(|spans| spans.iter())
+                .map(|span| TraceEvent {
+                    name: &span.name,
+                    // Group apps together
+                    cat: if span.name.starts_with("app/") { "app" } else { "shell" },
+                    ph: "X",
+                    ts: micros(span.start),
+                    dur: micros(span.duration),
+                    pid: 1,
+                    tid: 1,
+                })
+                .collect(),
+        };
+        let file = BufWriter::new(File::create(path)?);
+        serde_json::to_writer(file, &trace).map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    fn histogram(durations: &[(u32, u64)]) -> Histogram {
+        let mut hist = Histogram::new();
+        for &(times, ms) in durations {
+            for _ in 0..times {
+                hist.add(Duration::from_millis(ms));
+            }
+        }
+        hist
+    }
+
+    #[test]
+    fn recent_and_max() {
+        let mut hist = histogram(&[(1, 4)]);
+        assert!(close(hist.recent_ms(), 4.));
+        hist.add(Duration::from_millis(8));
+        assert!(close(hist.recent_ms(), 4. * (1. - RECENT_WEIGHT) + 8. * RECENT_WEIGHT));
+        assert!(close(hist.max_ms(), 8.));
+        assert_eq!(hist.count, 2);
+    }
+
+    #[test]
+    fn percentiles() {
+        let hist = histogram(&[(90, 1), (9, 10), (1, 2000)]);
+        // 1 ms falls in the bucket up to 1024 microseconds
+        assert!(close(hist.percentile_ms(0.5), 1.024));
+        assert!(close(hist.percentile_ms(0.9), 1.024));
+        assert!(close(hist.percentile_ms(0.95), 16.384));
+        // The last bucket is unbounded
+        assert!(close(hist.percentile_ms(1.), 2000.));
+    }
+
+    #[test]
+    fn percentile_capped_by_max() {
+        let hist = histogram(&[(10, 10)]);
+        assert!(close(hist.percentile_ms(0.5), 10.));
+    }
+
+    #[test]
+    fn empty() {
+        let hist = Histogram::new();
+        assert!(close(hist.percentile_ms(0.95), 0.));
+        assert!(close(hist.max_ms(), 0.));
+    }
+}
@@ -0,0 +1,121 @@
+//! A JSON format for describing worlds without writing code.
+//!
+//! A scene is a list of objects. Each object is drawn from an object directory
+//! (see `open_object_directory`), and may have a collision shape, a physics
+//! material, and a behavior that decides how it can be interacted with.
+
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+
+use serde_json;
+use nalgebra::{self as na, Vector3, Isometry3};
+
+pub use common::collision::{ShapeDesc, PartDesc};
+
+fn zero() -> Vector3<f32> { na::zero() }
+fn one() -> f32 { 1. }
+
+/// A world made of objects.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SceneDesc {
+    pub objects: Vec<ObjectDesc>,
+}
+
+impl SceneDesc {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<SceneDesc> {
+        let file = BufReader::new(File::open(path)?);
+        serde_json::from_reader(file)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+/// Something in a scene.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ObjectDesc {
+    /// Object directory containing the mesh.
+    pub asset: PathBuf,
+    #[serde(default)]
+    pub transform: TransformDesc,
+    /// Collision shape in object space, before scaling. Defaults to the
+    /// `collision.json` of the asset. Objects without a shape are only drawn.
+    #[serde(default)]
+    pub shape: Option<ShapeDesc>,
+    #[serde(default)]
+    pub material: MaterialDesc,
+    #[serde(default)]
+    pub behavior: Behavior,
+}
+
+/// Where an object is placed in the world.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransformDesc {
+    #[serde(default = "zero")]
+    pub position: Vector3<f32>,
+    /// Axis of rotation, scaled by the angle in radians.
+    #[serde(default = "zero")]
+    pub rotation: Vector3<f32>,
+    /// Uniform scale of the mesh and shape.
+    #[serde(default = "one")]
+    pub scale: f32,
+}
+
+impl TransformDesc {
+    pub fn isometry(&self) -> Isometry3<f32> {
+        Isometry3::new(self.position, self.rotation)
+    }
+}
+
+impl Default for TransformDesc {
+    fn default() -> Self {
+        TransformDesc {
+            position: na::zero(),
+            rotation: na::zero(),
+            scale: 1.,
+        }
+    }
+}
+
+/// Physical properties of an object.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct MaterialDesc {
+    /// Only used for dynamic objects.
+    #[serde(default = "default_density")]
+    pub density: f32,
+    #[serde(default = "default_restitution")]
+    pub restitution: f32,
+    #[serde(default = "default_friction")]
+    pub friction: f32,
+}
+
+fn default_density() -> f32 { 100. }
+fn default_restitution() -> f32 { 0. }
+fn default_friction() -> f32 { 0.8 }
+
+impl Default for MaterialDesc {
+    fn default() -> Self {
+        MaterialDesc {
+            density: default_density(),
+            restitution: default_restitution(),
+            friction: default_friction(),
+        }
+    }
+}
+
+/// How an object behaves in the world.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Behavior {
+    /// Never moves, but blocks lasers and other objects.
+    Static,
+    /// Moved by physics.
+    Dynamic,
+    /// Moved by physics and can be grabbed or yanked.
+    Grabbable,
+}
+
+impl Default for Behavior {
+    fn default() -> Self {
+        Behavior::Static
+    }
+}
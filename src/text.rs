@@ -0,0 +1,318 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use gfx::{self, Factory};
+use gfx::format::{DepthStencil, Rgba8, R8, Unorm};
+use gfx::memory::{Bind, Usage};
+use gfx::texture;
+use gfx::traits::FactoryExt;
+use nalgebra::{Matrix4, Transform3};
+use rusttype::{FontCollection, Scale, point};
+
+use flight::Error;
+use flight::draw::DrawParams;
+
+/// Width of the atlas texture, in pixels.
+const ATLAS_WIDTH: u32 = 512;
+/// Empty pixels left around each glyph so that filtering doesn't bleed.
+const GLYPH_PADDING: u32 = 1;
+/// The longest line of text that can be drawn at once.
+const MAX_LINE_LEN: usize = 256;
+
+/// Characters included in every atlas.
+pub const ATLAS_CHARS: &'static str =
+    " !\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~";
+
+/// Where a single character lives in the atlas.
+#[derive(Debug, Copy, Clone)]
+pub struct Glyph {
+    /// Texture coordinates of the top left and bottom right corners.
+    pub uv: ([f32; 2], [f32; 2]),
+    /// Size of the glyph's bitmap, in pixels.
+    pub size: (u32, u32),
+    /// Offset of the top left pixel from the glyph origin, in pixels (y down).
+    pub offset: (i32, i32),
+    /// Horizontal distance to the next glyph, in pixels.
+    pub advance: f32,
+}
+
+/// Coverage of the printable ASCII characters of a TrueType font, packed into
+/// a single single-channel image.
+pub struct GlyphAtlas {
+    pub glyphs: HashMap<char, Glyph>,
+    /// Coverage values, row by row from the top.
+    pub pixels: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+    /// The pixel height the glyphs were rasterized at.
+    pub px: f32,
+    /// Height above the baseline, in pixels.
+    pub ascent: f32,
+}
+
+impl GlyphAtlas {
+    /// Rasterize the printable ASCII characters of the given TrueType font at
+    /// a height of `px` pixels.
+    pub fn open<P: AsRef<Path>>(path: P, px: f32) -> io::Result<GlyphAtlas> {
+        let mut bytes = Vec::new();
+        File::open(path)?.read_to_end(&mut bytes)?;
+        let font = FontCollection::from_bytes(bytes)
+            .into_font()
+            .ok_or(io::Error::new(io::ErrorKind::InvalidData, "not a single TrueType font"))?;
+
+        let scale = Scale::uniform(px);
+        let glyphs: Vec<_> = ATLAS_CHARS.chars()
+            .filter_map(|c| font.glyph(c).map(|g| (c, g.scaled(scale))))
+            .map(|(c, g)| (c, g.h_metrics().advance_width, g.positioned(point(0., 0.))))
+            .collect();
+
+        // Pack the glyphs into rows from left to right
+        let row_height = glyphs.iter()
+            .filter_map(|&(_, _, ref g)| g.pixel_bounding_box())
+            .map(|bb| bb.height() as u32)
+            .max()
+            .unwrap_or(0) + GLYPH_PADDING;
+        let mut corners = Vec::with_capacity(glyphs.len());
+        let (mut x, mut y) = (GLYPH_PADDING, GLYPH_PADDING);
+        for &(_, _, ref glyph) in &glyphs {
+            let width = glyph.pixel_bounding_box().map(|bb| bb.width() as u32).unwrap_or(0);
+            if x + width + GLYPH_PADDING > ATLAS_WIDTH {
+                x = GLYPH_PADDING;
+                y += row_height;
+            }
+            corners.push((x, y));
+            x += width + GLYPH_PADDING;
+        }
+        let height = (y + row_height).next_power_of_two();
+
+        let mut pixels = vec![0; (ATLAS_WIDTH * height) as usize];
+        let mut map = HashMap::new();
+        for (&(c, advance, ref glyph), &(x, y)) in glyphs.iter().zip(&corners) {
+            let (size, offset) = match glyph.pixel_bounding_box() {
+                Some(bb) => {
+                    glyph.draw(|gx, gy, v| {
+                        pixels[((y + gy) * ATLAS_WIDTH + x + gx) as usize] = (v * 255.) as u8;
+                    });
+                    ((bb.width() as u32, bb.height() as u32), (bb.min.x, bb.min.y))
+                },
+                None => ((0, 0), (0, 0)),
+            };
+            let uv = |px: u32, py: u32| [px as f32 / ATLAS_WIDTH as f32, py as f32 / height as f32];
+            map.insert(c, Glyph {
+                uv: (uv(x, y), uv(x + size.0, y + size.1)),
+                size,
+                offset,
+                advance,
+            });
+        }
+
+        Ok(GlyphAtlas {
+            glyphs: map,
+            pixels,
+            width: ATLAS_WIDTH,
+            height,
+            px,
+            ascent: font.v_metrics(scale).ascent,
+        })
+    }
+}
+
+/// Horizontal alignment of a line of text relative to its origin.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+gfx_defines! {
+    vertex GlyphVert {
+        pos: [f32; 3] = "a_pos",
+        tex: [f32; 2] = "a_tex",
+    }
+
+    pipeline text_pipe {
+        verts: gfx::VertexBuffer<GlyphVert> = (),
+        matrix: gfx::Global<[[f32; 4]; 4]> = "u_matrix",
+        clip_offset: gfx::Global<f32> = "u_clip_offset",
+        color: gfx::Global<[f32; 4]> = "u_color",
+        atlas: gfx::TextureSampler<f32> = "t_atlas",
+        out_color: gfx::BlendTarget<Rgba8> =
+            ("o_color", gfx::state::ColorMask::all(), gfx::preset::blend::ALPHA),
+        out_depth: gfx::DepthTarget<DepthStencil> = gfx::preset::depth::LESS_EQUAL_WRITE,
+        scissor: gfx::Scissor = (),
+    }
+}
+
+const TEXT_VERT: &'static [u8] = b"
+#version 150 core
+
+in vec3 a_pos;
+in vec2 a_tex;
+uniform mat4 u_matrix;
+uniform float u_clip_offset;
+out vec2 v_tex;
+
+void main() {
+    v_tex = a_tex;
+    gl_Position = u_matrix * vec4(a_pos, 1.0);
+    gl_Position.x = gl_Position.x * 0.5 + u_clip_offset * gl_Position.w;
+}
+";
+
+const TEXT_FRAG: &'static [u8] = b"
+#version 150 core
+
+in vec2 v_tex;
+uniform sampler2D t_atlas;
+uniform vec4 u_color;
+out vec4 o_color;
+
+void main() {
+    float coverage = texture(t_atlas, v_tex).r;
+    if (coverage < 0.02) discard;
+    o_color = vec4(u_color.rgb, u_color.a * coverage);
+}
+";
+
+fn gfx_error<E: Debug>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, format!("{:?}", e))
+}
+
+/// Column-major form of a matrix, as expected by GLSL.
+fn columns(m: &Matrix4<f32>) -> [[f32; 4]; 4] {
+    let mut out = [[0.; 4]; 4];
+    for c in 0..4 {
+        for r in 0..4 {
+            out[c][r] = m[(r, c)];
+        }
+    }
+    out
+}
+
+/// Draws text as textured quads sampled from a `GlyphAtlas`. Text is one unit
+/// tall, with the baseline along the x axis and the front facing +z.
+pub struct TextPainter<R: gfx::Resources> {
+    pso: gfx::PipelineState<R, text_pipe::Meta>,
+    texture: gfx::handle::Texture<R, R8>,
+    view: gfx::handle::ShaderResourceView<R, f32>,
+    sampler: gfx::handle::Sampler<R>,
+    verts: gfx::handle::Buffer<R, GlyphVert>,
+    /// Atlas pixels waiting to be copied into the texture by the first draw.
+    pending: Option<Vec<u8>>,
+    glyphs: HashMap<char, Glyph>,
+    px: f32,
+    color: [f32; 4],
+}
+
+impl<R: gfx::Resources> TextPainter<R> {
+    pub fn new<F: gfx::Factory<R>>(factory: &mut F, atlas: &GlyphAtlas, color: [f32; 3])
+        -> Result<TextPainter<R>, Error>
+    {
+        let pso = factory.create_pipeline_simple(TEXT_VERT, TEXT_FRAG, text_pipe::new())
+            .map_err(gfx_error)?;
+
+        let kind = texture::Kind::D2(atlas.width as u16, atlas.height as u16, texture::AaMode::Single);
+        let texture = factory.create_texture::<R8>(
+                kind, 1, Bind::SHADER_RESOURCE, Usage::Dynamic, Some(gfx::format::ChannelType::Unorm))
+            .map_err(gfx_error)?;
+        let view = factory.view_texture_as_shader_resource::<(R8, Unorm)>(&texture, (0, 0), gfx::format::Swizzle::new())
+            .map_err(gfx_error)?;
+        let verts = factory.create_buffer(MAX_LINE_LEN * 6, gfx::buffer::Role::Vertex, Usage::Dynamic, Bind::empty())
+            .map_err(gfx_error)?;
+
+        Ok(TextPainter {
+            pso,
+            texture,
+            view,
+            sampler: factory.create_sampler_linear(),
+            verts,
+            pending: Some(atlas.pixels.clone()),
+            glyphs: atlas.glyphs.clone(),
+            px: atlas.px,
+            color: [color[0], color[1], color[2], 1.],
+        })
+    }
+
+    /// The width of the given line of text, relative to its height.
+    pub fn measure(&self, text: &str) -> f32 {
+        text.chars()
+            .filter_map(|c| self.glyphs.get(&c))
+            .map(|g| g.advance / self.px)
+            .sum()
+    }
+
+    /// Draw a line of text. Characters missing from the atlas are skipped.
+    pub fn draw<C: gfx::CommandBuffer<R>>(
+        &mut self,
+        params: &mut DrawParams<R, C>,
+        trans: Transform3<f32>,
+        text: &str,
+        align: Align,
+    ) {
+        if let Some(pixels) = self.pending.take() {
+            let info = self.texture.get_info().to_image_info(0);
+            if let Err(e) = params.encoder.update_texture::<R8, (R8, Unorm)>(&self.texture, None, info, &pixels) {
+                error!("Could not upload glyph atlas: {:?}", e);
+            }
+        }
+
+        let mut x = match align {
+            Align::Left => 0.,
+            Align::Center => -self.measure(text) / 2.,
+            Align::Right => -self.measure(text),
+        };
+        let mut verts = Vec::with_capacity(text.len() * 6);
+        for c in text.chars().take(MAX_LINE_LEN) {
+            let glyph = match self.glyphs.get(&c) {
+                Some(g) => g,
+                None => continue,
+            };
+            if glyph.size.0 > 0 {
+                let left = x + glyph.offset.0 as f32 / self.px;
+                let right = left + glyph.size.0 as f32 / self.px;
+                let top = -glyph.offset.1 as f32 / self.px;
+                let bottom = top - glyph.size.1 as f32 / self.px;
+                let ([u0, v0], [u1, v1]) = glyph.uv;
+                let tl = GlyphVert { pos: [left, top, 0.], tex: [u0, v0] };
+                let tr = GlyphVert { pos: [right, top, 0.], tex: [u1, v0] };
+                let bl = GlyphVert { pos: [left, bottom, 0.], tex: [u0, v1] };
+                let br = GlyphVert { pos: [right, bottom, 0.], tex: [u1, v1] };
+                verts.extend_from_slice(&[bl, br, tr, bl, tr, tl]);
+            }
+            x += glyph.advance / self.px;
+        }
+        if verts.is_empty() { return }
+        if let Err(e) = params.encoder.update_buffer(&self.verts, &verts, 0) {
+            error!("Could not upload text: {:?}", e);
+            return;
+        }
+
+        let slice = gfx::Slice {
+            start: 0,
+            end: verts.len() as u32,
+            base_vertex: 0,
+            instances: None,
+            buffer: gfx::IndexBuffer::Auto,
+        };
+        let model = *trans.matrix();
+        for eye in &[&params.left, &params.right] {
+            let view: Matrix4<f32> = eye.view.into();
+            let proj: Matrix4<f32> = eye.proj.into();
+            let data = text_pipe::Data {
+                verts: self.verts.clone(),
+                matrix: columns(&(proj * view * model)),
+                clip_offset: eye.clip_offset,
+                color: self.color,
+                atlas: (self.view.clone(), self.sampler.clone()),
+                out_color: params.color.clone(),
+                out_depth: params.depth.clone(),
+                scissor: eye.clip,
+            };
+            params.encoder.draw(&slice, &self.pso, &data);
+        }
+    }
+}
@@ -0,0 +1,237 @@
+use interact::{InteractGuru, InteractionReply};
+use common::CommonReply;
+use nalgebra::{self as na, Isometry3, Vector2, Vector3, Translation3};
+use ncollide::shape::{Cuboid};
+use gfx;
+
+use super::{Grip, Widget, clicked, scaled};
+
+/// A box that can be clicked by pointing at it or touching it and pulling the
+/// trigger. Unless it is locked, it can be moved by yanking it.
+pub struct Button {
+    pub position: Isometry3<f32>,
+    pub half_extents: Vector3<f32>,
+    /// Locked buttons can not be moved (e.g. when they are part of another
+    /// widget).
+    pub locked: bool,
+    grip: Grip,
+}
+
+/// The result of updating a `Button`.
+#[derive(Debug, Copy, Clone)]
+pub struct ButtonData {
+    pub clicked: bool,
+    pub position: Isometry3<f32>,
+}
+
+impl Button {
+    pub fn new(position: Isometry3<f32>, half_extents: Vector3<f32>) -> Self {
+        Button {
+            position,
+            half_extents,
+            locked: false,
+            grip: Grip::clickable(),
+        }
+    }
+
+    /// Create a button that can not be moved on its own.
+    pub fn locked(position: Isometry3<f32>, half_extents: Vector3<f32>) -> Self {
+        Button {
+            locked: true,
+            ..Button::new(position, half_extents)
+        }
+    }
+
+    /// Update the button without drawing it.
+    pub fn update_silent<'a>(
+        &'a mut self,
+        interact: &mut InteractGuru,
+    )
+        -> impl FnOnce(&InteractionReply)
+        -> ButtonData + 'a
+    {
+        let shape = Cuboid::new(self.half_extents);
+        let (grip, click) = if self.locked {
+            (None, Some(clicked(interact, &self.position, &shape)))
+        } else {
+            (Some(self.grip.update(
+                interact,
+                self.position,
+                &shape,
+                Isometry3::from_parts(Translation3::new(0., 0., -0.2), na::one()),
+                |_| true,
+            )), None)
+        };
+
+        let position = &mut self.position;
+        move |reply| {
+            let mut was_clicked = false;
+            if let Some(grip) = grip {
+                let data = grip(reply);
+                if let Some(pos) = data.moved_to() {
+                    *position = pos;
+                }
+                was_clicked = data.clicked;
+            }
+            if let Some(click) = click {
+                was_clicked = click(reply);
+            }
+            ButtonData {
+                clicked: was_clicked,
+                position: *position,
+            }
+        }
+    }
+
+    /// Update and draw the button, highlighting it if `active` is true. The
+    /// returned future completes with `true` if the button was clicked this
+    /// frame.
+    pub fn update<'a, R, C>(
+        &'a mut self,
+        interact: &mut InteractGuru,
+        active: bool,
+    )
+        -> impl FnOnce(&mut CommonReply<R, C>)
+        -> bool + 'a
+        where R: gfx::Resources, C: gfx::CommandBuffer<R>
+    {
+        let half_extents = self.half_extents;
+        let state = self.update_silent(interact);
+        move |reply| {
+            let data = state(&reply.reply.interact);
+            reply.painters.uber.draw(
+                &mut reply.draw_params,
+                data.position * scaled(half_extents),
+                if active || data.clicked { &reply.meshes.button_active } else { &reply.meshes.button },
+            );
+            data.clicked
+        }
+    }
+}
+
+/// A button that flips between on and off when clicked.
+pub struct Toggle {
+    pub value: bool,
+    pub button: Button,
+}
+
+impl Toggle {
+    pub fn new(position: Isometry3<f32>, half_extents: Vector3<f32>, value: bool) -> Self {
+        Toggle {
+            value,
+            button: Button::new(position, half_extents),
+        }
+    }
+
+    pub fn update<'a, R, C>(
+        &'a mut self,
+        interact: &mut InteractGuru,
+    )
+        -> impl FnOnce(&mut CommonReply<R, C>)
+        -> bool + 'a
+        where R: gfx::Resources, C: gfx::CommandBuffer<R>
+    {
+        let half_extents = self.button.half_extents;
+        let state = self.button.update_silent(interact);
+        let value = &mut self.value;
+        move |reply| {
+            let data = state(&reply.reply.interact);
+            if data.clicked {
+                *value = !*value;
+            }
+            reply.painters.uber.draw(
+                &mut reply.draw_params,
+                data.position * scaled(half_extents),
+                if *value { &reply.meshes.button_active } else { &reply.meshes.button },
+            );
+            *value
+        }
+    }
+}
+
+/// A toggle drawn as a wire frame that is filled in when checked.
+pub struct Checkbox {
+    pub toggle: Toggle,
+}
+
+impl Checkbox {
+    pub fn new(position: Isometry3<f32>, size: f32, value: bool) -> Self {
+        Checkbox {
+            toggle: Toggle::new(position, Vector3::new(size, size, size), value),
+        }
+    }
+
+    pub fn update<'a, R, C>(
+        &'a mut self,
+        interact: &mut InteractGuru,
+    )
+        -> impl FnOnce(&mut CommonReply<R, C>)
+        -> bool + 'a
+        where R: gfx::Resources, C: gfx::CommandBuffer<R>
+    {
+        let half_extents = self.toggle.button.half_extents;
+        let state = self.toggle.button.update_silent(interact);
+        let value = &mut self.toggle.value;
+        move |reply| {
+            let data = state(&reply.reply.interact);
+            if data.clicked {
+                *value = !*value;
+            }
+            reply.painters.solid.draw(
+                &mut reply.draw_params,
+                data.position * scaled(half_extents * 2.),
+                &reply.meshes.wire_box,
+            );
+            if *value {
+                reply.painters.uber.draw(
+                    &mut reply.draw_params,
+                    data.position * scaled(half_extents * 0.6),
+                    &reply.meshes.button_active,
+                );
+            }
+            *value
+        }
+    }
+}
+
+impl Widget for Button {
+    fn extents(&self) -> Vector2<f32> {
+        Vector2::new(self.half_extents.x, self.half_extents.y)
+    }
+
+    fn place(&mut self, pose: Isometry3<f32>) {
+        self.position = pose;
+    }
+
+    fn set_locked(&mut self, locked: bool) {
+        self.locked = locked;
+    }
+}
+
+impl Widget for Toggle {
+    fn extents(&self) -> Vector2<f32> {
+        self.button.extents()
+    }
+
+    fn place(&mut self, pose: Isometry3<f32>) {
+        self.button.place(pose)
+    }
+
+    fn set_locked(&mut self, locked: bool) {
+        self.button.set_locked(locked)
+    }
+}
+
+impl Widget for Checkbox {
+    fn extents(&self) -> Vector2<f32> {
+        self.toggle.extents()
+    }
+
+    fn place(&mut self, pose: Isometry3<f32>) {
+        self.toggle.place(pose)
+    }
+
+    fn set_locked(&mut self, locked: bool) {
+        self.toggle.set_locked(locked)
+    }
+}
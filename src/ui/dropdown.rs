@@ -0,0 +1,133 @@
+use interact::InteractGuru;
+use common::CommonReply;
+use nalgebra::{Isometry3, Vector2, Vector3, Translation3};
+use gfx;
+
+use text::Align;
+use super::{Button, Widget, scaled};
+
+/// A button that opens a list of options when clicked. Clicking an option
+/// selects it and closes the list.
+pub struct Dropdown {
+    pub options: Vec<String>,
+    pub selected: usize,
+    pub open: bool,
+    pub header: Button,
+    items: Vec<Button>,
+}
+
+impl Dropdown {
+    pub fn new(header: Button, options: Vec<String>, selected: usize) -> Self {
+        let items = options.iter()
+            .map(|_| Button::locked(header.position, header.half_extents))
+            .collect();
+        Dropdown {
+            options,
+            selected,
+            open: false,
+            header,
+            items,
+        }
+    }
+
+    /// The currently selected option.
+    pub fn value(&self) -> Option<&str> {
+        self.options.get(self.selected).map(|s| s.as_str())
+    }
+
+    pub fn update<'a, R, C>(
+        &'a mut self,
+        interact: &mut InteractGuru,
+    )
+        -> impl FnOnce(&mut CommonReply<R, C>)
+        -> usize + 'a
+        where R: gfx::Resources, C: gfx::CommandBuffer<R>
+    {
+        let half_extents = self.header.half_extents;
+        let spacing = half_extents.y * 2.2;
+
+        // Keep the item list the same length as the options
+        let base = self.header.position;
+        while self.items.len() < self.options.len() {
+            self.items.push(Button::locked(base, half_extents));
+        }
+        self.items.truncate(self.options.len());
+
+        let items: Vec<_> = if self.open {
+            self.items.iter_mut().enumerate().map(|(i, item)| {
+                item.position = base * Translation3::new(0., -spacing * (i + 1) as f32, 0.);
+                item.half_extents = half_extents;
+                item.update_silent(interact)
+            }).collect()
+        } else {
+            Vec::new()
+        };
+        let header = self.header.update_silent(interact);
+
+        let options = &self.options;
+        let open = &mut self.open;
+        let selected = &mut self.selected;
+        move |reply| {
+            let header = header(&reply.reply.interact);
+            let items: Vec<_> = items.into_iter().map(|i| i(&reply.reply.interact)).collect();
+
+            for (i, item) in items.iter().enumerate() {
+                if item.clicked {
+                    *selected = i;
+                    *open = false;
+                }
+            }
+            if header.clicked {
+                *open = !*open;
+            }
+
+            // Text sits just in front of each button
+            let text_offset = Translation3::new(0., -half_extents.y * 0.6, half_extents.z * 1.1);
+            let text_size = Vector3::new(half_extents.y * 1.6, half_extents.y * 1.6, half_extents.y * 1.6);
+
+            reply.painters.uber.draw(
+                &mut reply.draw_params,
+                header.position * scaled(half_extents),
+                if *open { &reply.meshes.button_active } else { &reply.meshes.button },
+            );
+            if let Some(value) = options.get(*selected) {
+                reply.painters.text.draw(
+                    &mut reply.draw_params,
+                    header.position * text_offset * scaled(text_size),
+                    value,
+                    Align::Center,
+                );
+            }
+            for (i, item) in items.iter().enumerate() {
+                reply.painters.uber.draw(
+                    &mut reply.draw_params,
+                    item.position * scaled(Vector3::new(half_extents.x * 0.9, half_extents.y, half_extents.z)),
+                    if i == *selected { &reply.meshes.button_active } else { &reply.meshes.button },
+                );
+                reply.painters.text.draw(
+                    &mut reply.draw_params,
+                    item.position * text_offset * scaled(text_size),
+                    &options[i],
+                    Align::Center,
+                );
+            }
+
+            *selected
+        }
+    }
+}
+
+impl Widget for Dropdown {
+    /// Only the header is included, the list of options hangs below it.
+    fn extents(&self) -> Vector2<f32> {
+        self.header.extents()
+    }
+
+    fn place(&mut self, pose: Isometry3<f32>) {
+        self.header.place(pose)
+    }
+
+    fn set_locked(&mut self, locked: bool) {
+        self.header.set_locked(locked)
+    }
+}
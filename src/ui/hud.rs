@@ -0,0 +1,79 @@
+use common::CommonReply;
+use interact::ControllerIndex;
+use nalgebra::{Isometry3, Vector3, Translation3, UnitQuaternion};
+use gfx;
+
+use profile::Profiler;
+use text::Align;
+use super::Label;
+
+/// Frame timings floating above the primary controller.
+pub struct ProfilerHud {
+    /// Pose of the first line relative to the primary controller.
+    pub offset: Isometry3<f32>,
+    /// Height of a line of text.
+    pub line_height: f32,
+    pub visible: bool,
+}
+
+impl ProfilerHud {
+    pub fn new(visible: bool) -> Self {
+        ProfilerHud {
+            offset: Isometry3::from_parts(
+                Translation3::new(-0.05, 0.2, 0.),
+                UnitQuaternion::from_axis_angle(&Vector3::x_axis(), -0.5),
+            ),
+            line_height: 0.012,
+            visible,
+        }
+    }
+
+    /// The lines shown: the whole frame, each shell phase, then the cost of
+    /// each app with its update and reply added together.
+    fn lines(profiler: &Profiler) -> Vec<String> {
+        let mut lines = Vec::new();
+        if let Some(frame) = profiler.histogram("frame") {
+            lines.push(format!(
+                "frame {:5.2} ms  p95 {:5.2}  max {:5.2}",
+                frame.recent_ms(),
+                frame.percentile_ms(0.95),
+                frame.max_ms(),
+            ));
+        }
+        let mut apps: Vec<(&str, f64)> = Vec::new();
+        for (name, hist) in profiler.histograms() {
+            if name == "frame" {
+                continue;
+            }
+            if name.starts_with("app/") {
+                let app = name["app/".len()..].split('/').next().unwrap_or("");
+                let existing = apps.iter().position(|&(a, _)| a == app);
+                match existing {
+                    Some(i) => apps[i].1 += hist.recent_ms(),
+                    None => apps.push((app, hist.recent_ms())),
+                }
+            } else {
+                lines.push(format!("{:<16} {:5.2} ms", name, hist.recent_ms()));
+            }
+        }
+        for (app, ms) in apps {
+            lines.push(format!("{:<16} {:5.2} ms", app, ms));
+        }
+        lines
+    }
+
+    /// Draw the timings, if visible.
+    pub fn draw<R, C>(&self, reply: &mut CommonReply<R, C>, profiler: &Profiler)
+        where R: gfx::Resources, C: gfx::CommandBuffer<R>
+    {
+        if !self.visible {
+            return;
+        }
+        for (i, line) in ProfilerHud::lines(profiler).into_iter().enumerate() {
+            let pos = self.offset * Translation3::new(0., -(i as f32) * self.line_height * 1.4, 0.);
+            let mut label = Label::attached(line, ControllerIndex::primary(), pos, self.line_height);
+            label.align = Align::Left;
+            label.draw(reply);
+        }
+    }
+}
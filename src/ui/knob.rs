@@ -0,0 +1,105 @@
+use std::f32::consts::PI;
+
+use interact::InteractGuru;
+use common::CommonReply;
+use nalgebra::{self as na, Isometry3, Vector2, Vector3, Translation3, UnitQuaternion};
+use ncollide::shape::{Cylinder};
+use gfx;
+
+use super::{Grip, Widget, scaled};
+
+/// A dial that is turned by grabbing it and twisting the controller.
+pub struct Knob {
+    pub value: f32,
+    pub position: Isometry3<f32>,
+    pub radius: f32,
+    /// The angle (in radians) the knob turns through from 0 to 1.
+    pub sweep: f32,
+    grip: Grip,
+}
+
+impl Knob {
+    pub fn new(position: Isometry3<f32>, radius: f32, value: f32) -> Self {
+        Knob {
+            value,
+            position,
+            radius,
+            sweep: 1.5 * PI,
+            grip: Default::default(),
+        }
+    }
+
+    pub fn update<'a, R, C>(
+        &'a mut self,
+        interact: &mut InteractGuru,
+    )
+        -> impl FnOnce(&mut CommonReply<R, C>)
+        -> f32 + 'a
+        where R: gfx::Resources, C: gfx::CommandBuffer<R>
+    {
+        // Cylinders are aligned along the y axis, knobs face along z
+        let shape_pos = self.position * UnitQuaternion::rotation_between(
+            &Vector3::new(0., 1., 0.),
+            &Vector3::new(0., 0., 1.),
+        ).unwrap();
+        let grip = self.grip.update(
+            interact,
+            shape_pos,
+            &Cylinder::new(self.radius * 0.4, self.radius),
+            Isometry3::from_parts(Translation3::new(0., 0., -0.2), na::one()),
+            |_| true,
+        );
+
+        let value = &mut self.value;
+        let position = &mut self.position;
+        let radius = self.radius;
+        let sweep = self.sweep;
+        move |reply| {
+            let grip_data = grip(&reply.reply.interact);
+
+            if let (Some(_), Some(ref fix)) = (grip_data.manipulated(), grip_data.fixed.as_ref()) {
+                let con = fix.by.reply(&reply.reply.interact);
+                let axis = position.rotation * Vector3::z();
+                let twist = con.data.pose_delta.rotation.scaled_axis().dot(&axis);
+                *value = (*value + twist / sweep).max(0.).min(1.);
+            }
+            if let Some(pos) = grip_data.moved_to() {
+                *position = pos * UnitQuaternion::rotation_between(
+                    &Vector3::new(0., 0., 1.),
+                    &Vector3::new(0., 1., 0.),
+                ).unwrap();
+            }
+
+            reply.painters.uber.draw(
+                &mut reply.draw_params,
+                (*position) * scaled(Vector3::new(radius, radius, radius * 0.4)),
+                &reply.meshes.button,
+            );
+            let angle = (*value - 0.5) * sweep;
+            reply.painters.uber.draw(
+                &mut reply.draw_params,
+                (*position)
+                    * UnitQuaternion::from_axis_angle(&Vector3::z_axis(), -angle)
+                    * Translation3::new(0., radius * 0.7, radius * 0.4)
+                    * scaled(Vector3::new(radius * 0.1, radius * 0.2, radius * 0.1)),
+                &reply.meshes.button_active,
+            );
+
+            *value
+        }
+    }
+}
+
+impl Widget for Knob {
+    fn extents(&self) -> Vector2<f32> {
+        Vector2::new(self.radius, self.radius)
+    }
+
+    fn place(&mut self, pose: Isometry3<f32>) {
+        self.position = pose;
+    }
+
+    fn set_locked(&mut self, locked: bool) {
+        self.grip.locked = locked;
+    }
+}
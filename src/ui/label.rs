@@ -0,0 +1,87 @@
+use interact::ControllerIndex;
+use common::CommonReply;
+use nalgebra::{Isometry3, Vector2, Vector3, Translation3};
+use gfx;
+
+use text::Align;
+use super::{Widget, scaled};
+
+/// What a label's position is relative to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Anchor {
+    World,
+    Controller(ControllerIndex),
+}
+
+/// A line of text floating in the world or attached to a controller.
+pub struct Label {
+    pub text: String,
+    pub position: Isometry3<f32>,
+    /// Height of a line of text, in meters.
+    pub height: f32,
+    pub align: Align,
+    pub anchor: Anchor,
+}
+
+impl Label {
+    pub fn new<S: Into<String>>(text: S, position: Isometry3<f32>, height: f32) -> Self {
+        Label {
+            text: text.into(),
+            position,
+            height,
+            align: Align::Center,
+            anchor: Anchor::World,
+        }
+    }
+
+    /// Create a label that follows a controller, offset by `position`.
+    pub fn attached<S: Into<String>>(
+        text: S,
+        index: ControllerIndex,
+        position: Isometry3<f32>,
+        height: f32,
+    ) -> Self {
+        Label {
+            anchor: Anchor::Controller(index),
+            ..Label::new(text, position, height)
+        }
+    }
+
+    /// Draw the label right away.
+    pub fn draw<R, C>(&self, reply: &mut CommonReply<R, C>)
+        where R: gfx::Resources, C: gfx::CommandBuffer<R>
+    {
+        let pos = match self.anchor {
+            Anchor::World => self.position,
+            Anchor::Controller(index) => index.reply(&reply.reply.interact).data.pose * self.position,
+        };
+        reply.painters.text.draw(
+            &mut reply.draw_params,
+            pos * scaled(Vector3::new(self.height, self.height, self.height)),
+            &self.text,
+            self.align,
+        );
+    }
+
+    pub fn update<'a, R, C>(&'a self) -> impl FnOnce(&mut CommonReply<R, C>) + 'a
+        where R: gfx::Resources, C: gfx::CommandBuffer<R>
+    {
+        move |reply| self.draw(reply)
+    }
+}
+
+impl Widget for Label {
+    /// Estimated from the length of the text, since the font is monospaced.
+    fn extents(&self) -> Vector2<f32> {
+        Vector2::new(self.text.chars().count() as f32 * 0.3 * self.height, self.height / 2.)
+    }
+
+    fn place(&mut self, pose: Isometry3<f32>) {
+        self.anchor = Anchor::World;
+        self.align = Align::Center;
+        self.position = pose * Translation3::new(0., -self.height * 0.35, 0.);
+    }
+
+    /// Labels can't be grabbed, so there is nothing to lock.
+    fn set_locked(&mut self, _: bool) {}
+}
@@ -0,0 +1,240 @@
+use interact::InteractGuru;
+use common::CommonReply;
+use nalgebra::{self as na, Isometry3, Vector2, Vector3, Translation3, UnitQuaternion};
+use ncollide::shape::{Cuboid};
+use gfx;
+
+use super::{Grip, scaled};
+
+/// Something that can be positioned by a layout container. Widgets are laid
+/// out on a plane facing +z, with x to the right and y up.
+pub trait Widget {
+    /// Half of the width and height the widget takes up.
+    fn extents(&self) -> Vector2<f32>;
+
+    /// Move the center of the widget to the given pose.
+    fn place(&mut self, pose: Isometry3<f32>);
+
+    /// Stop the widget from being moved on its own, while still letting it be
+    /// used.
+    fn set_locked(&mut self, locked: bool);
+}
+
+/// The result of laying out a set of widgets.
+#[derive(Debug, Clone)]
+pub struct Layout {
+    /// Pose of each widget relative to the container's center.
+    pub poses: Vec<Isometry3<f32>>,
+    /// Half of the width and height of the container.
+    pub extents: Vector2<f32>,
+}
+
+/// Computes the poses of widgets from their sizes.
+pub trait Container {
+    fn layout(&self, sizes: &[Vector2<f32>]) -> Layout;
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Axis {
+    Horizontal,
+    Vertical,
+}
+
+/// Places widgets in a row or column, in order from left to right or top to
+/// bottom.
+#[derive(Debug, Clone)]
+pub struct Stack {
+    pub axis: Axis,
+    /// Space between the widgets and the edge of the container.
+    pub padding: f32,
+    /// Space between neighboring widgets.
+    pub spacing: f32,
+}
+
+impl Stack {
+    pub fn vertical(padding: f32, spacing: f32) -> Self {
+        Stack { axis: Axis::Vertical, padding, spacing }
+    }
+
+    pub fn horizontal(padding: f32, spacing: f32) -> Self {
+        Stack { axis: Axis::Horizontal, padding, spacing }
+    }
+}
+
+impl Container for Stack {
+    fn layout(&self, sizes: &[Vector2<f32>]) -> Layout {
+        // `main` runs along the stack, `cross` across it
+        let (main, cross) = match self.axis {
+            Axis::Horizontal => (0, 1),
+            Axis::Vertical => (1, 0),
+        };
+        let gaps = sizes.len().saturating_sub(1) as f32 * self.spacing;
+        let length: f32 = sizes.iter().map(|s| s[main] * 2.).sum::<f32>() + gaps;
+        let width = sizes.iter().map(|s| s[cross]).fold(0., |a: f32, b| a.max(b));
+
+        let mut cursor = -length / 2.;
+        let poses = sizes.iter().map(|s| {
+            let center = cursor + s[main];
+            cursor += s[main] * 2. + self.spacing;
+            match self.axis {
+                Axis::Horizontal => Isometry3::from_parts(Translation3::new(center, 0., 0.), na::one()),
+                Axis::Vertical => Isometry3::from_parts(Translation3::new(0., -center, 0.), na::one()),
+            }
+        }).collect();
+
+        let mut extents = Vector2::new(0., 0.);
+        extents[main] = length / 2. + self.padding;
+        extents[cross] = width + self.padding;
+        Layout { poses, extents }
+    }
+}
+
+/// Places widgets in equally sized cells, filling each row from left to right
+/// before moving down.
+#[derive(Debug, Clone)]
+pub struct Grid {
+    pub columns: usize,
+    pub padding: f32,
+    pub spacing: f32,
+}
+
+impl Container for Grid {
+    fn layout(&self, sizes: &[Vector2<f32>]) -> Layout {
+        let columns = self.columns.max(1);
+        let rows = (sizes.len() + columns - 1) / columns;
+        let cell = sizes.iter().fold(Vector2::new(0., 0.), |c, s| Vector2::new(c.x.max(s.x), c.y.max(s.y)));
+        let step = cell * 2. + Vector2::new(self.spacing, self.spacing);
+        let half = Vector2::new(
+            (step.x * columns.min(sizes.len()) as f32 - self.spacing).max(0.) / 2.,
+            (step.y * rows as f32 - self.spacing).max(0.) / 2.,
+        );
+
+        let poses = (0..sizes.len()).map(|i| {
+            let (col, row) = ((i % columns) as f32, (i / columns) as f32);
+            Isometry3::from_parts(
+                Translation3::new(
+                    -half.x + cell.x + col * step.x,
+                    half.y - cell.y - row * step.y,
+                    0.,
+                ),
+                na::one(),
+            )
+        }).collect();
+
+        Layout {
+            poses,
+            extents: half + Vector2::new(self.padding, self.padding),
+        }
+    }
+}
+
+/// Places widgets in a row along an arc that curves toward the viewer, so that
+/// every widget faces the center of the circle.
+#[derive(Debug, Clone)]
+pub struct Curved {
+    /// Distance from the center of the circle to the panel.
+    pub radius: f32,
+    pub padding: f32,
+    pub spacing: f32,
+}
+
+impl Container for Curved {
+    fn layout(&self, sizes: &[Vector2<f32>]) -> Layout {
+        let flat = Stack::horizontal(self.padding, self.spacing).layout(sizes);
+        let center = Vector3::new(0., 0., self.radius);
+        let poses = flat.poses.iter().map(|p| {
+            // Wrap the flat horizontal position around the circle
+            let angle = p.translation.vector.x / self.radius;
+            let rot = UnitQuaternion::from_axis_angle(&Vector3::y_axis(), -angle);
+            Isometry3::from_parts(
+                Translation3::from_vector(center + rot * Vector3::new(0., 0., -self.radius)),
+                rot,
+            )
+        }).collect();
+        Layout { poses, extents: flat.extents }
+    }
+}
+
+/// A group of widgets laid out by a container that can be moved as a unit by
+/// grabbing or yanking the bar along its bottom edge.
+pub struct Panel<L: Container> {
+    pub position: Isometry3<f32>,
+    pub container: L,
+    /// Height of the grab bar.
+    pub bar: f32,
+    extents: Vector2<f32>,
+    grip: Grip,
+}
+
+impl<L: Container> Panel<L> {
+    pub fn new(position: Isometry3<f32>, container: L) -> Self {
+        Panel {
+            position,
+            container,
+            bar: 0.05,
+            extents: Vector2::new(0., 0.),
+            grip: Default::default(),
+        }
+    }
+
+    /// Half of the width and height of the panel from the last arrangement.
+    pub fn extents(&self) -> Vector2<f32> {
+        self.extents
+    }
+
+    /// Place and lock the given widgets according to the container.
+    pub fn arrange(&mut self, widgets: &mut [&mut Widget]) {
+        let sizes: Vec<_> = widgets.iter().map(|w| w.extents()).collect();
+        let layout = self.container.layout(&sizes);
+        for (w, pose) in widgets.iter_mut().zip(layout.poses) {
+            w.set_locked(true);
+            w.place(self.position * pose);
+        }
+        self.extents = layout.extents;
+    }
+
+    /// Update the grab bar and arrange the widgets around where it is this
+    /// frame. Widgets should be updated after calling this.
+    pub fn update<'a, R, C>(
+        &'a mut self,
+        interact: &mut InteractGuru,
+        widgets: &mut [&mut Widget],
+    )
+        -> impl FnOnce(&mut CommonReply<R, C>) + 'a
+        where R: gfx::Resources, C: gfx::CommandBuffer<R>
+    {
+        let sizes: Vec<_> = widgets.iter().map(|w| w.extents()).collect();
+        self.extents = self.container.layout(&sizes).extents;
+
+        let bar_half = Vector3::new(self.extents.x, self.bar / 2., self.bar / 2.);
+        let bar_offset = Translation3::new(0., -self.extents.y - self.bar, 0.);
+        let bar_pos = self.position * bar_offset;
+        let inv_yank_offset = Isometry3::from_parts(Translation3::new(0., 0., -0.3), na::one());
+
+        // Follow the bar if it is being moved, so the widgets don't trail it
+        if let Some(pos) = self.grip.predict(interact, bar_pos, inv_yank_offset) {
+            self.position = pos * bar_offset.inverse();
+        }
+        self.arrange(widgets);
+
+        let grip = self.grip.update(
+            interact,
+            bar_pos,
+            &Cuboid::new(bar_half),
+            inv_yank_offset,
+            |_| false,
+        );
+
+        let position = &mut self.position;
+        move |reply| {
+            if let Some(pos) = grip(&reply.reply.interact).moved_to() {
+                *position = pos * bar_offset.inverse();
+            }
+            reply.painters.uber.draw(
+                &mut reply.draw_params,
+                (*position) * bar_offset * scaled(bar_half),
+                &reply.meshes.button,
+            );
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use nalgebra::{Point3, Vector3, Vector4, Matrix4, Isometry3, Transform3};
+use ncollide::shape::Shape;
+
+use interact::{InteractGuru, InteractionReply, Moveable, MoveableIntention, ControllerIndex, Fixed};
+
+pub mod slider;
+pub mod button;
+pub mod knob;
+pub mod pad;
+pub mod dropdown;
+pub mod stepper;
+pub mod label;
+pub mod layout;
+pub mod wrist;
+pub mod hud;
+
+pub use self::slider::{Slider, SliderRange, SliderEvent, SliderData, Scale};
+pub use self::button::{Button, Toggle, Checkbox};
+pub use self::knob::Knob;
+pub use self::pad::Pad;
+pub use self::dropdown::Dropdown;
+pub use self::stepper::NumericStepper;
+pub use self::label::{Label, Anchor};
+pub use self::layout::{Widget, Container, Layout, Stack, Grid, Curved, Panel};
+pub use self::wrist::WristMenu;
+pub use self::hud::ProfilerHud;
+
+/// How a widget is currently being held.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WidgetMode {
+    Unheld,
+    Moving,
+    Manipulating,
+}
+
+/// Time (in seconds) it takes a yanked widget to reach the controller.
+const YANK_SPEED: f32 = 0.2;
+
+/// Shared grab behavior for widgets. Yanking a widget always moves it, while
+/// grabbing it will either manipulate or move it depending on where it was
+/// first grabbed.
+#[derive(Debug, Clone)]
+pub struct Grip {
+    mov: Moveable,
+    mode: WidgetMode,
+    /// If the widget can be grabbed by touching it and pulling the trigger.
+    /// Otherwise doing so clicks it.
+    grabbable: bool,
+    /// Locked widgets can still be manipulated, but are never moved (e.g.
+    /// when they are placed by a `Panel`).
+    pub locked: bool,
+}
+
+/// The result of updating a `Grip`.
+#[derive(Debug, Clone)]
+pub struct GripData {
+    pub mode: WidgetMode,
+    pub fixed: Option<Fixed>,
+    /// If the widget was clicked this frame, see `clicked`. Only widgets that
+    /// can't be grabbed are clicked.
+    pub clicked: bool,
+}
+
+impl Default for Grip {
+    fn default() -> Self {
+        Grip {
+            mov: Default::default(),
+            mode: WidgetMode::Unheld,
+            grabbable: true,
+            locked: false,
+        }
+    }
+}
+
+impl Grip {
+    /// A grip for clickable widgets, which can only be moved by yanking them.
+    pub fn clickable() -> Self {
+        Grip {
+            grabbable: false,
+            ..Default::default()
+        }
+    }
+
+    pub fn mode(&self) -> WidgetMode {
+        self.mode
+    }
+
+    /// Where the widget at `pos` will be moved this frame if it is already
+    /// being moved. Unlike `GripData::moved_to`, this is available before the
+    /// interaction guru is resolved, so that dependent widgets can follow it
+    /// without lagging a frame behind.
+    pub fn predict(
+        &self,
+        interact: &mut InteractGuru,
+        pos: Isometry3<f32>,
+        inv_yank_offset: Isometry3<f32>,
+    ) -> Option<Isometry3<f32>> {
+        if self.mode != WidgetMode::Moving || self.locked {
+            return None;
+        }
+        self.mov.predict(interact, pos, inv_yank_offset, YANK_SPEED).map(|fix| fix.pos)
+    }
+
+    /// Update the grip of a widget at `pos` with the given shape. When the
+    /// widget is first grabbed, `on_control` is given the pose of the
+    /// controller relative to the widget and decides if the widget should be
+    /// manipulated (`true`) or moved (`false`).
+    pub fn update<'a, F>(
+        &'a mut self,
+        interact: &mut InteractGuru,
+        pos: Isometry3<f32>,
+        shape: &Shape<Point3<f32>, Isometry3<f32>>,
+        inv_yank_offset: Isometry3<f32>,
+        on_control: F,
+    )
+        -> impl FnOnce(&InteractionReply)
+        -> GripData + 'a
+        where F: FnOnce(&Isometry3<f32>) -> bool + 'a
+    {
+        use self::WidgetMode::*;
+
+        let grabbable = self.grabbable;
+        let locked = self.locked;
+        let mov = self.mov.update(interact, pos, shape, inv_yank_offset, YANK_SPEED, grabbable, !locked);
+        let mode = &mut self.mode;
+        move |reply| {
+            let mov_data = mov(reply);
+            match mov_data.fixed {
+                Some(ref fix) if mov_data.intent == MoveableIntention::Manipulate => {
+                    if *mode == Unheld {
+                        *mode = if on_control(&fix.inv_offset.inverse()) {
+                            Manipulating
+                        } else {
+                            Moving
+                        };
+                    }
+                },
+                Some(_) => *mode = Moving,
+                None => *mode = Unheld,
+            }
+            GripData {
+                mode: *mode,
+                // Locked widgets stay put while held
+                fixed: if locked && *mode == Moving { None } else { mov_data.fixed },
+                clicked: !grabbable && mov_data.pressed.is_some(),
+            }
+        }
+    }
+}
+
+impl GripData {
+    /// The new position of the widget, if it is being moved.
+    pub fn moved_to(&self) -> Option<Isometry3<f32>> {
+        match (self.mode, &self.fixed) {
+            (WidgetMode::Moving, &Some(ref fix)) => Some(fix.pos),
+            _ => None,
+        }
+    }
+
+    /// The pose of the holding controller relative to the widget, if it is
+    /// being manipulated.
+    pub fn manipulated(&self) -> Option<Isometry3<f32>> {
+        match (self.mode, &self.fixed) {
+            (WidgetMode::Manipulating, &Some(ref fix)) => Some(fix.inv_offset.inverse()),
+            _ => None,
+        }
+    }
+}
+
+/// Check if either controller clicked the given shape this frame, by pointing
+/// at it or touching it while the trigger is pressed. Widgets that can be
+/// moved get this from their `Grip` instead, which already points at them.
+pub fn clicked(
+    interact: &mut InteractGuru,
+    pos: &Isometry3<f32>,
+    shape: &Shape<Point3<f32>, Isometry3<f32>>,
+)
+    -> impl FnOnce(&InteractionReply)
+    -> bool
+{
+    let cons: Vec<_> = (&[ControllerIndex::primary(), ControllerIndex::secondary()])
+        .into_iter()
+        .map(|&idx| {
+            let guru = idx.guru(interact);
+            (idx, guru.pointing_laser(pos, shape, true), guru.touched(pos, shape))
+        }).collect();
+
+    move |reply| {
+        let mut clicked = false;
+        for (idx, pointed, touched) in cons {
+            let con = idx.reply(reply);
+            let pressed = con.data.trigger > 0.5 && con.data.trigger - con.data.trigger_delta < 0.5;
+            if (pointed(reply).is_some() || touched(reply)) && pressed {
+                clicked = true;
+            }
+        }
+        clicked
+    }
+}
+
+/// Transform that scales a unit mesh to the given half extents.
+pub fn scaled(half_extents: Vector3<f32>) -> Transform3<f32> {
+    Transform3::from_matrix_unchecked(
+        Matrix4::from_diagonal(&Vector4::new(half_extents.x, half_extents.y, half_extents.z, 1.))
+    )
+}
@@ -0,0 +1,99 @@
+use interact::InteractGuru;
+use common::CommonReply;
+use nalgebra::{self as na, Isometry3, Vector2, Vector3, Translation3};
+use ncollide::shape::{Cuboid};
+use gfx;
+
+use super::{Grip, Widget, scaled};
+
+/// A flat square that reports a 2D value from where it is grabbed.
+pub struct Pad {
+    /// Both components range from 0 to 1.
+    pub value: Vector2<f32>,
+    pub position: Isometry3<f32>,
+    pub size: f32,
+    pub thickness: f32,
+    grip: Grip,
+}
+
+impl Pad {
+    pub fn new(position: Isometry3<f32>, size: f32, value: Vector2<f32>) -> Self {
+        Pad {
+            value,
+            position,
+            size,
+            thickness: 0.05,
+            grip: Default::default(),
+        }
+    }
+
+    pub fn update<'a, R, C>(
+        &'a mut self,
+        interact: &mut InteractGuru,
+    )
+        -> impl FnOnce(&mut CommonReply<R, C>)
+        -> Vector2<f32> + 'a
+        where R: gfx::Resources, C: gfx::CommandBuffer<R>
+    {
+        let half = Vector3::new(self.size / 2., self.size / 2., self.thickness / 2.);
+        // Grabbing the face manipulates, grabbing the rim moves
+        let rim = self.size * 0.05;
+        let grip = self.grip.update(
+            interact,
+            self.position,
+            &Cuboid::new(half),
+            Isometry3::from_parts(Translation3::new(0., 0., -0.2), na::one()),
+            move |local| {
+                let p = local.translation.vector;
+                p.x.abs() < half.x - rim && p.y.abs() < half.y - rim
+            },
+        );
+
+        let value = &mut self.value;
+        let position = &mut self.position;
+        let size = self.size;
+        move |reply| {
+            let grip_data = grip(&reply.reply.interact);
+
+            if let Some(local) = grip_data.manipulated() {
+                let p = local.translation.vector;
+                *value = Vector2::new(
+                    (p.x / size + 0.5).max(0.).min(1.),
+                    (p.y / size + 0.5).max(0.).min(1.),
+                );
+            }
+            if let Some(pos) = grip_data.moved_to() {
+                *position = pos;
+            }
+
+            reply.painters.solid.draw(
+                &mut reply.draw_params,
+                (*position) * scaled(half * 2.),
+                &reply.meshes.wire_box,
+            );
+            reply.painters.uber.draw(
+                &mut reply.draw_params,
+                (*position)
+                    * Translation3::new((value.x - 0.5) * size, (value.y - 0.5) * size, 0.)
+                    * scaled(Vector3::new(half.z, half.z, half.z)),
+                &reply.meshes.button_active,
+            );
+
+            *value
+        }
+    }
+}
+
+impl Widget for Pad {
+    fn extents(&self) -> Vector2<f32> {
+        Vector2::new(self.size / 2., self.size / 2.)
+    }
+
+    fn place(&mut self, pose: Isometry3<f32>) {
+        self.position = pose;
+    }
+
+    fn set_locked(&mut self, locked: bool) {
+        self.grip.locked = locked;
+    }
+}
@@ -0,0 +1,381 @@
+use interact::{InteractGuru, HapticPulse};
+use common::CommonReply;
+use nalgebra::{Isometry3, Vector2, Vector3, Translation3, UnitQuaternion};
+use ncollide::shape::{Cuboid};
+use gfx;
+
+use text::Align;
+use super::{Grip, Widget, scaled};
+
+/// Most detents that will be drawn along a slider.
+const MAX_DETENTS: usize = 20;
+/// Strength of the pulse felt when the value snaps to the next step.
+const DETENT_STRENGTH: f32 = 0.3;
+/// Length of a detent pulse, in seconds.
+const DETENT_DURATION: f32 = 0.005;
+
+/// How the position of the control maps to the value of a slider.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Scale {
+    Linear,
+    /// Equal distances along the slider multiply the value by equal amounts.
+    /// The range must be strictly positive.
+    Logarithmic,
+}
+
+/// The values a slider can take.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SliderRange {
+    pub min: f32,
+    pub max: f32,
+    pub scale: Scale,
+    /// If set, values snap to multiples of the step above `min`.
+    pub step: Option<f32>,
+}
+
+impl SliderRange {
+    pub fn linear(min: f32, max: f32) -> Self {
+        SliderRange { min, max, scale: Scale::Linear, step: None }
+    }
+
+    pub fn logarithmic(min: f32, max: f32) -> Self {
+        SliderRange { min, max, scale: Scale::Logarithmic, step: None }
+    }
+
+    /// The position (from 0 to 1) of the given value along the slider.
+    pub fn fraction(&self, value: f32) -> f32 {
+        let t = match self.scale {
+            Scale::Linear => (value - self.min) / (self.max - self.min),
+            Scale::Logarithmic => (value / self.min).ln() / (self.max / self.min).ln(),
+        };
+        if t.is_finite() { t.max(0.).min(1.) } else { 0. }
+    }
+
+    /// The value at the given position (from 0 to 1) along the slider.
+    pub fn value(&self, fraction: f32) -> f32 {
+        let t = fraction.max(0.).min(1.);
+        let value = match self.scale {
+            Scale::Linear => self.min + t * (self.max - self.min),
+            Scale::Logarithmic => self.min * (self.max / self.min).powf(t),
+        };
+        self.snap(value)
+    }
+
+    /// Round the value to the nearest step and clamp it to the range.
+    pub fn snap(&self, value: f32) -> f32 {
+        let value = match self.step {
+            Some(step) if step > 0. => self.min + ((value - self.min) / step).round() * step,
+            _ => value,
+        };
+        value.max(self.min).min(self.max)
+    }
+
+    /// The values of each step, if there are few enough to show.
+    pub fn detents(&self) -> Vec<f32> {
+        match self.step {
+            Some(step) if step > 0. && (self.max - self.min) / step <= MAX_DETENTS as f32 => {
+                let count = ((self.max - self.min) / step).floor() as usize;
+                (0..(count + 1)).map(|i| self.min + i as f32 * step).collect()
+            },
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Something that happened to a slider during a frame.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SliderEvent {
+    /// The control was grabbed with the given value.
+    Grabbed(f32),
+    /// The value changed while the control was held.
+    Changed { from: f32, to: f32 },
+    /// The control was let go with the given value.
+    Released(f32),
+    /// The value was reset to its default.
+    Reset(f32),
+}
+
+/// The result of updating a `Slider`.
+#[derive(Debug, Clone)]
+pub struct SliderData {
+    pub value: f32,
+    pub events: Vec<SliderEvent>,
+}
+
+pub struct Slider {
+    pub value: f32,
+    pub range: SliderRange,
+    /// The value the slider is reset to.
+    pub default: f32,
+    /// Shown with the value at the end of the slider when not empty.
+    pub label: String,
+    /// Shown after the value.
+    pub unit: String,
+    /// Number of decimal places shown.
+    pub precision: usize,
+    pub position: Isometry3<f32>,
+    pub thickness: f32,
+    pub length: f32,
+    pub manip_length: f32,
+    grip: Grip,
+    sliding: bool,
+    reset: bool,
+}
+
+impl Slider {
+    /// Create a slider from 0 to 1, which defaults to the given value.
+    pub fn new(position: Isometry3<f32>, thickness: f32, length: f32, manip_length: f32, value: f32) -> Self {
+        Slider {
+            value: value,
+            range: SliderRange::linear(0., 1.),
+            default: value,
+            label: String::new(),
+            unit: String::new(),
+            precision: 2,
+            position,
+            thickness,
+            length,
+            manip_length,
+            grip: Default::default(),
+            sliding: false,
+            reset: false,
+        }
+    }
+
+    /// Set the value back to the default. A `SliderEvent::Reset` will be
+    /// reported by the next update.
+    pub fn reset(&mut self) {
+        self.value = self.default;
+        self.reset = true;
+    }
+
+    /// The value with the label and units.
+    pub fn text(&self) -> String {
+        format_value(&self.label, self.value, self.precision, &self.unit)
+    }
+
+    pub fn update<'a, R, C>(
+        &'a mut self,
+        interact: &mut InteractGuru,
+    )
+        -> impl FnOnce(&mut CommonReply<R, C>)
+        -> SliderData + 'a
+        where R: gfx::Resources, C: gfx::CommandBuffer<R>
+    {
+        let range = self.range;
+        let cap = (0.6 / 10.) * self.length;
+        let true_len = self.length - cap - self.manip_length;
+        let current_pos = (range.fraction(self.value) - 0.5) * true_len;
+        let slider_r = self.manip_length / 2.;
+
+        let scaled_shape = Vector3::new(self.thickness / 2., self.thickness / 2., self.length / 2.);
+        let grip = self.grip.update(
+            interact,
+            self.position,
+            &Cuboid::new(scaled_shape),
+            Isometry3::from_parts(
+                Translation3::new(0., 0., -self.thickness / 2.),
+                UnitQuaternion::rotation_between(
+                    &Vector3::new(0., 0., 1.),
+                    &Vector3::new(0., 1., 0.),
+                ).unwrap(),
+            ),
+            move |local| (current_pos - local.translation.vector[2]).abs() < slider_r,
+        );
+
+        let mut events = Vec::new();
+        if self.reset {
+            events.push(SliderEvent::Reset(self.value));
+            self.reset = false;
+        }
+
+        let value = &mut self.value;
+        let sliding = &mut self.sliding;
+        let label = &self.label;
+        let unit = &self.unit;
+        let precision = self.precision;
+        let position = &mut self.position;
+        let manip_length = self.manip_length;
+        let length = self.length;
+        let thickness = self.thickness;
+        move |reply| {
+            let grip_data = grip(&reply.reply.interact);
+
+            match (grip_data.manipulated(), *sliding) {
+                (Some(local), was_sliding) => {
+                    if !was_sliding {
+                        events.push(SliderEvent::Grabbed(*value));
+                    }
+                    let next_pos = local.translation.vector[2];
+                    let next = range.value((next_pos / true_len) + 0.5);
+                    if next != *value {
+                        events.push(SliderEvent::Changed { from: *value, to: next });
+                        *value = next;
+                        if let (Some(_), Some(ref fix)) = (range.step, grip_data.fixed.as_ref()) {
+                            reply.meta.pulses.push(HapticPulse {
+                                index: fix.by,
+                                strength: DETENT_STRENGTH,
+                                duration: DETENT_DURATION,
+                            });
+                        }
+                    }
+                    *sliding = true;
+                },
+                (None, true) => {
+                    events.push(SliderEvent::Released(*value));
+                    *sliding = false;
+                },
+                (None, false) => (),
+            }
+            if let Some(pos) = grip_data.moved_to() {
+                *position = pos;
+            }
+
+            reply.painters.uber.draw(
+                &mut reply.draw_params,
+                (*position) * scaled(Vector3::new(thickness / 2., thickness / 2., length / 10.)),
+                &reply.meshes.slider_frame,
+            );
+            for detent in range.detents() {
+                let detent_pos = (range.fraction(detent) - 0.5) * true_len;
+                reply.painters.uber.draw(
+                    &mut reply.draw_params,
+                    (*position) * Translation3::new(0., thickness / 2., detent_pos)
+                        * scaled(Vector3::new(thickness / 2., thickness / 20., thickness / 40.)),
+                    &reply.meshes.button,
+                );
+            }
+            let slider_pos = (range.fraction(*value) - 0.5) * true_len;
+            reply.painters.uber.draw(
+                &mut reply.draw_params,
+                (*position) * Translation3::new(0., 0., slider_pos)
+                    * scaled(Vector3::new(thickness / 2., thickness / 2., manip_length / 2.)),
+                &reply.meshes.slider_control,
+            );
+            if !label.is_empty() {
+                reply.painters.text.draw(
+                    &mut reply.draw_params,
+                    (*position) * Translation3::new(0., 0., length / 2. + thickness)
+                        * scaled(Vector3::new(thickness / 2., thickness / 2., thickness / 2.)),
+                    &format_value(label, *value, precision, unit),
+                    Align::Center,
+                );
+            }
+
+            SliderData {
+                value: *value,
+                events,
+            }
+        }
+    }
+}
+
+fn format_value(label: &str, value: f32, precision: usize, unit: &str) -> String {
+    let mut text = format!("{:.*}", precision, value);
+    if !unit.is_empty() {
+        text.push(' ');
+        text.push_str(unit);
+    }
+    if !label.is_empty() {
+        text = format!("{}: {}", label, text);
+    }
+    text
+}
+
+impl Widget for Slider {
+    fn extents(&self) -> Vector2<f32> {
+        Vector2::new(self.length / 2., self.thickness / 2.)
+    }
+
+    /// Sliders are laid out horizontally.
+    fn place(&mut self, pose: Isometry3<f32>) {
+        self.position = pose * UnitQuaternion::rotation_between(
+            &Vector3::new(0., 0., 1.),
+            &Vector3::new(1., 0., 0.),
+        ).unwrap();
+    }
+
+    fn set_locked(&mut self, locked: bool) {
+        self.grip.locked = locked;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-5, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn linear_round_trip() {
+        let range = SliderRange::linear(-2., 6.);
+        assert_close(range.fraction(-2.), 0.);
+        assert_close(range.fraction(6.), 1.);
+        assert_close(range.value(0.), -2.);
+        assert_close(range.value(1.), 6.);
+        for &v in &[-2., -1.5, 0., 3.25, 6.] {
+            assert_close(range.value(range.fraction(v)), v);
+        }
+    }
+
+    #[test]
+    fn logarithmic_round_trip() {
+        let range = SliderRange::logarithmic(0.1, 10.);
+        assert_close(range.fraction(0.1), 0.);
+        assert_close(range.fraction(1.), 0.5);
+        assert_close(range.fraction(10.), 1.);
+        assert_close(range.value(0.), 0.1);
+        assert_close(range.value(0.5), 1.);
+        assert_close(range.value(1.), 10.);
+        for &v in &[0.1, 0.25, 1., 4., 10.] {
+            assert_close(range.value(range.fraction(v)), v);
+        }
+    }
+
+    #[test]
+    fn out_of_range_is_clamped() {
+        let range = SliderRange::linear(0., 1.);
+        assert_eq!(range.fraction(-1.), 0.);
+        assert_eq!(range.fraction(2.), 1.);
+        assert_eq!(range.value(-0.5), 0.);
+        assert_eq!(range.value(1.5), 1.);
+
+        let range = SliderRange::logarithmic(1., 100.);
+        assert_eq!(range.fraction(0.), 0.);
+        assert_eq!(range.fraction(-5.), 0.);
+        assert_eq!(range.fraction(1000.), 1.);
+    }
+
+    #[test]
+    fn snapping() {
+        let range = SliderRange {
+            step: Some(0.1),
+            ..SliderRange::linear(0.2, 0.8)
+        };
+        assert_close(range.snap(0.34), 0.3);
+        assert_close(range.snap(0.36), 0.4);
+        assert_close(range.snap(0.), 0.2);
+        assert_close(range.snap(1.), 0.8);
+        assert_close(range.value(0.), 0.2);
+        assert_close(range.value(0.5), 0.5);
+        assert_close(range.value(0.52), 0.5);
+        assert_close(range.value(1.), 0.8);
+
+        let detents = range.detents();
+        assert_eq!(detents.len(), 7);
+        assert_close(detents[0], 0.2);
+        assert_close(detents[6], 0.8);
+    }
+
+    #[test]
+    fn too_many_detents_are_hidden() {
+        let range = SliderRange {
+            step: Some(0.01),
+            ..SliderRange::linear(0., 1.)
+        };
+        assert!(range.detents().is_empty());
+        assert!(SliderRange::linear(0., 1.).detents().is_empty());
+    }
+}
@@ -0,0 +1,100 @@
+use interact::InteractGuru;
+use common::CommonReply;
+use nalgebra::{Isometry3, Vector2, Vector3, Translation3};
+use gfx;
+
+use super::{Button, Widget, scaled};
+
+/// A value that is increased or decreased by fixed steps using a pair of
+/// buttons on either side of a body. The body can be yanked to move the
+/// whole widget.
+pub struct NumericStepper {
+    pub value: f32,
+    pub min: f32,
+    pub max: f32,
+    pub step: f32,
+    pub body: Button,
+    decrease: Button,
+    increase: Button,
+}
+
+impl NumericStepper {
+    pub fn new(body: Button, min: f32, max: f32, step: f32, value: f32) -> Self {
+        let half = body.half_extents;
+        let side = Vector3::new(half.y, half.y, half.z);
+        NumericStepper {
+            value: value.max(min).min(max),
+            min,
+            max,
+            step,
+            decrease: Button::locked(body.position, side),
+            increase: Button::locked(body.position, side),
+            body,
+        }
+    }
+
+    pub fn update<'a, R, C>(
+        &'a mut self,
+        interact: &mut InteractGuru,
+    )
+        -> impl FnOnce(&mut CommonReply<R, C>)
+        -> f32 + 'a
+        where R: gfx::Resources, C: gfx::CommandBuffer<R>
+    {
+        let half = self.body.half_extents;
+        let side = Vector3::new(half.y, half.y, half.z);
+        let offset = half.x + half.y * 1.2;
+        self.decrease.position = self.body.position * Translation3::new(-offset, 0., 0.);
+        self.decrease.half_extents = side;
+        self.increase.position = self.body.position * Translation3::new(offset, 0., 0.);
+        self.increase.half_extents = side;
+
+        let decrease = self.decrease.update(interact, false);
+        let increase = self.increase.update(interact, false);
+        let body = self.body.update_silent(interact);
+
+        let value = &mut self.value;
+        let (min, max, step) = (self.min, self.max, self.step);
+        move |reply| {
+            let body = body(&reply.reply.interact);
+            if decrease(reply) {
+                *value = (*value - step).max(min);
+            }
+            if increase(reply) {
+                *value = (*value + step).min(max);
+            }
+
+            // Show the value as a bar filling the body
+            let fill = if max > min { (*value - min) / (max - min) } else { 0. };
+            reply.painters.solid.draw(
+                &mut reply.draw_params,
+                body.position * scaled(half * 2.),
+                &reply.meshes.wire_box,
+            );
+            reply.painters.uber.draw(
+                &mut reply.draw_params,
+                body.position
+                    * Translation3::new(half.x * (fill - 1.), 0., 0.)
+                    * scaled(Vector3::new((half.x * fill).max(0.001), half.y * 0.8, half.z * 0.8)),
+                &reply.meshes.button_active,
+            );
+
+            *value
+        }
+    }
+}
+
+impl Widget for NumericStepper {
+    fn extents(&self) -> Vector2<f32> {
+        let half = self.body.half_extents;
+        Vector2::new(half.x + half.y * 2.2, half.y)
+    }
+
+    fn place(&mut self, pose: Isometry3<f32>) {
+        self.body.place(pose)
+    }
+
+    fn set_locked(&mut self, locked: bool) {
+        self.body.set_locked(locked)
+    }
+}
@@ -0,0 +1,99 @@
+use std::mem;
+use std::f32::consts::FRAC_PI_2;
+
+use common::{Common, CommonReply};
+use nalgebra::{Isometry3, Vector2, Vector3, Translation3, UnitQuaternion};
+use gfx;
+
+use text::Align;
+use super::{Button, Container, Stack, scaled};
+
+/// A panel of buttons that follows the secondary controller and appears when
+/// its wrist is turned palm up. Entries are registered by apps through the
+/// `MenuGuru`.
+pub struct WristMenu {
+    /// Pose of the menu relative to the secondary controller.
+    pub offset: Isometry3<f32>,
+    /// Half extents of each entry.
+    pub entry_size: Vector3<f32>,
+    pub open: bool,
+    buttons: Vec<Button>,
+    chosen: Vec<String>,
+}
+
+impl WristMenu {
+    pub fn new() -> Self {
+        WristMenu {
+            offset: Isometry3::from_parts(
+                Translation3::new(0.12, 0., 0.05),
+                UnitQuaternion::from_axis_angle(&Vector3::y_axis(), FRAC_PI_2),
+            ),
+            entry_size: Vector3::new(0.08, 0.02, 0.005),
+            open: false,
+            buttons: Vec::new(),
+            chosen: Vec::new(),
+        }
+    }
+
+    /// Take the ids of the entries chosen on the last frame, to pass to the
+    /// next `MenuGuru`.
+    pub fn take_chosen(&mut self) -> Vec<String> {
+        mem::replace(&mut self.chosen, Vec::new())
+    }
+
+    /// Draw the entries registered so far and check for choices. This should
+    /// be called after every app has been updated.
+    pub fn update<'a, R, C>(
+        &'a mut self,
+        common: &mut Common<R, C>,
+    )
+        -> impl FnOnce(&mut CommonReply<R, C>) + 'a
+        where R: gfx::Resources, C: gfx::CommandBuffer<R>
+    {
+        let pose = common.gurus.interact.secondary.data.pose;
+        // Open when the side of the controller faces up
+        self.open = (pose.rotation * Vector3::x()).y > 0.7;
+
+        let entries = if self.open { common.gurus.menu.entries().to_vec() } else { Vec::new() };
+        let size = self.entry_size;
+        let sizes: Vec<_> = entries.iter().map(|_| Vector2::new(size.x, size.y)).collect();
+        let layout = Stack::vertical(0.01, 0.01).layout(&sizes);
+
+        while self.buttons.len() < entries.len() {
+            self.buttons.push(Button::locked(pose, size));
+        }
+        self.buttons.truncate(entries.len());
+
+        let base = pose * self.offset;
+        let buttons: Vec<_> = self.buttons.iter_mut()
+            .zip(layout.poses)
+            .map(|(button, local)| {
+                button.position = base * local;
+                button.half_extents = size;
+                button.update_silent(&mut common.gurus.interact)
+            })
+            .collect();
+
+        let chosen = &mut self.chosen;
+        move |reply| {
+            for (entry, button) in entries.into_iter().zip(buttons) {
+                let data = button(&reply.reply.interact);
+                reply.painters.uber.draw(
+                    &mut reply.draw_params,
+                    data.position * scaled(size),
+                    if data.clicked { &reply.meshes.button_active } else { &reply.meshes.button },
+                );
+                reply.painters.text.draw(
+                    &mut reply.draw_params,
+                    data.position * Translation3::new(0., -size.y * 0.6, size.z * 1.1)
+                        * scaled(Vector3::new(size.y * 1.6, size.y * 1.6, size.y * 1.6)),
+                    &entry.label,
+                    Align::Center,
+                );
+                if data.clicked {
+                    chosen.push(entry.id);
+                }
+            }
+        }
+    }
+}